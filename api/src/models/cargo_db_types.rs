@@ -1,6 +1,5 @@
 use redis_graph::{GraphResult, WithProperties};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 // Models
 #[derive(Debug, Clone, Serialize)]
@@ -9,8 +8,73 @@ pub struct CargoCrateVersionNode {
 
     pub id: i32,
     pub num: String,
-    pub features: HashMap<String, Vec<String>>,
     pub crate_name: String,
+    pub yanked: bool,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+    // Unix timestamp (seconds) of the publish date
+    pub created_at: Option<i64>,
+    // All-time / last 90 days downloads, of the version and of its crate (the latter filled in by traversal)
+    pub downloads: Option<i64>,
+    pub recent_downloads: Option<i64>,
+    pub crate_downloads: Option<i64>,
+    pub crate_recent_downloads: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoCrateNode {
+    pub node_id: u64,
+
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+    pub downloads: Option<i64>,
+    pub recent_downloads: Option<i64>,
+}
+
+// CargoUser or CargoTeam, both can publish new versions of the crates they own
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoOwnerNode {
+    pub node_id: u64,
+
+    pub kind: CargoOwnerKind,
+    pub id: i32,
+    // GitHub login of a user, "github:<org>:<team>" of a team
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CargoOwnerKind {
+    User,
+    Team,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoCategoryNode {
+    pub node_id: u64,
+
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoFeatureNode {
+    pub node_id: u64,
+
+    pub version_id: i32,
+    pub name: String,
+    // Raw entries, eg. "std", "dep:serde", "serde/std", "serde?/std"
+    pub enables: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -18,11 +82,57 @@ pub struct CargoDependsOnEdge {
     pub src_node_id: u64,
     pub dest_node_id: u64,
 
+    // Name the dependency is known by inside of the depending crate (differs from crate_name when renamed)
+    pub name: String,
+    pub required_semver: String,
     pub optional: bool,
     pub with_features: Vec<String>,
     pub kind: CargoDependencyKind,
 }
 
+// Only present in graphs imported with candidate edges
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoCanResolveToEdge {
+    pub src_node_id: u64,
+    pub dest_node_id: u64,
+
+    pub name: String,
+    pub required_semver: String,
+    pub target: Option<String>,
+    pub kind: CargoDependencyKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoCategoryWithCrateCount {
+    pub category: CargoCategoryNode,
+    pub crates: i64,
+}
+
+// Names of the traversed crates the owner can publish
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoTreeOwner {
+    pub owner: CargoOwnerNode,
+    pub crates: Vec<String>,
+}
+
+// Number of crates with a version depending on any version of the crate
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoCrateWithReverseDependencies {
+    #[serde(rename = "crate")]
+    pub crate_node: CargoCrateNode,
+    pub reverse_dependencies: i64,
+}
+
+// Every version a single dependency could resolve to
+#[derive(Debug, Clone, Serialize)]
+pub struct CargoDependencyCandidates {
+    pub name: String,
+    pub required_semver: String,
+    pub target: Option<String>,
+    pub kind: CargoDependencyKind,
+    pub versions: Vec<CargoCrateVersionNode>,
+}
+
 // Helper types
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[repr(i32)]
@@ -43,7 +153,9 @@ impl std::convert::From<i32> for CargoDependencyKind {
     }
 }
 
-// Custom parsing for each model
+// Custom parsing for each model.
+// Properties the preprocessor started writing later (yanked, created_at, downloads, dependency names and requirements)
+// are missing on graphs imported before, so they're read as optional / with a default.
 pub trait RedisGraphParser {
     fn parse(input: &GraphResult, data_variable_name: &str) -> anyhow::Result<Self>
     where
@@ -58,16 +170,6 @@ pub trait RedisGraphParser {
             .map(|s| RedisGraphParser::parse(s, data_variable_name))
             .collect()
     }
-
-    fn parse_string_to_vec(val: Option<String>) -> Option<Vec<String>> {
-        val.map(|s| {
-            s.trim_start_matches('[')
-                .trim_end_matches(']')
-                .split(',')
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>()
-        })
-    }
 }
 
 impl RedisGraphParser for CargoCrateVersionNode {
@@ -81,8 +183,105 @@ impl RedisGraphParser for CargoCrateVersionNode {
             node_id: node.id,
             id: node.get_property("id")?.unwrap(),
             num: node.get_property("num")?.unwrap(),
-            features: serde_json::from_str(&node.get_property::<String>("features")?.unwrap())?,
             crate_name: node.get_property("crate_name")?.unwrap(),
+            yanked: node
+                .get_property::<String>("yanked")?
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(false),
+            description: node.get_property("description")?,
+            repository: node.get_property("repository")?,
+            documentation: node.get_property("documentation")?,
+            homepage: node.get_property("homepage")?,
+            created_at: node.get_property("created_at")?,
+            downloads: node.get_property("downloads")?,
+            recent_downloads: node.get_property("recent_downloads")?,
+            crate_downloads: None,
+            crate_recent_downloads: None,
+        })
+    }
+}
+
+impl RedisGraphParser for CargoCrateNode {
+    fn parse(input: &GraphResult, data_variable_name: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let node = input.get_node(data_variable_name).unwrap();
+
+        Ok(CargoCrateNode {
+            node_id: node.id,
+            id: node.get_property("id")?.unwrap(),
+            name: node.get_property("name")?.unwrap(),
+            description: node.get_property("description")?,
+            repository: node.get_property("repository")?,
+            documentation: node.get_property("documentation")?,
+            homepage: node.get_property("homepage")?,
+            downloads: node.get_property("downloads")?,
+            recent_downloads: node.get_property("recent_downloads")?,
+        })
+    }
+}
+
+impl RedisGraphParser for CargoOwnerNode {
+    fn parse(input: &GraphResult, data_variable_name: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let node = input.get_node(data_variable_name).unwrap();
+
+        // Users and teams name the same things differently
+        if node.labels.iter().any(|s| s == "CargoTeam") {
+            Ok(CargoOwnerNode {
+                node_id: node.id,
+                kind: CargoOwnerKind::Team,
+                id: node.get_property("id")?.unwrap(),
+                login: node.get_property("login")?.unwrap(),
+                name: node.get_property("name")?,
+                avatar: node.get_property("avatar")?,
+            })
+        } else {
+            Ok(CargoOwnerNode {
+                node_id: node.id,
+                kind: CargoOwnerKind::User,
+                id: node.get_property("id")?.unwrap(),
+                login: node.get_property("gh_username")?.unwrap(),
+                name: node.get_property("preferred_name")?,
+                avatar: node.get_property("gh_avatar")?,
+            })
+        }
+    }
+}
+
+impl RedisGraphParser for CargoCategoryNode {
+    fn parse(input: &GraphResult, data_variable_name: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let node = input.get_node(data_variable_name).unwrap();
+
+        Ok(CargoCategoryNode {
+            node_id: node.id,
+            id: node.get_property("id")?.unwrap(),
+            name: node.get_property("name")?.unwrap(),
+            slug: node.get_property("slug")?.unwrap(),
+            description: node.get_property("description")?.unwrap_or_default(),
+        })
+    }
+}
+
+impl RedisGraphParser for CargoFeatureNode {
+    fn parse(input: &GraphResult, data_variable_name: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let node = input.get_node(data_variable_name).unwrap();
+
+        Ok(CargoFeatureNode {
+            node_id: node.id,
+            version_id: node.get_property("version_id")?.unwrap(),
+            name: node.get_property("name")?.unwrap(),
+            enables: node.get_property("enables")?.unwrap(),
         })
     }
 }
@@ -97,9 +296,30 @@ impl RedisGraphParser for CargoDependsOnEdge {
         Ok(CargoDependsOnEdge {
             src_node_id: edge.src_node,
             dest_node_id: edge.dest_node,
+            // Filled in by traversal when missing
+            name: edge.get_property("name")?.unwrap_or_default(),
+            required_semver: edge.get_property("required_semver")?.unwrap_or_default(),
             optional: edge.get_property::<String>("optional")?.unwrap().parse()?,
             with_features: edge.get_property("with_features")?.unwrap(),
             kind: edge.get_property::<i32>("kind")?.unwrap().into(),
         })
     }
 }
+
+impl RedisGraphParser for CargoCanResolveToEdge {
+    fn parse(input: &GraphResult, data_variable_name: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let edge = input.get_relation(data_variable_name).unwrap();
+
+        Ok(CargoCanResolveToEdge {
+            src_node_id: edge.src_node,
+            dest_node_id: edge.dest_node,
+            name: edge.get_property("name")?.unwrap(),
+            required_semver: edge.get_property("required_semver")?.unwrap(),
+            target: edge.get_property("target")?,
+            kind: edge.get_property::<i32>("kind")?.unwrap().into(),
+        })
+    }
+}
@@ -1,6 +1,13 @@
 use crate::{
     models::cargo_db_types::{CargoCrateVersionNode, RedisGraphParser},
-    utils::{app_error::AppError, cargo::traverse_tree, constants::CARGO_GRAPH_NAME},
+    utils::{
+        app_error::{AppError, BadRequest, NotFound},
+        cargo::{
+            get_candidates, get_categories, get_category_crates, get_tree_owners, traverse_tree,
+            DependencyResolution,
+        },
+        constants::{CARGO_GRAPH_NAME, IMPORT_STATS_KEY},
+    },
     AppState,
 };
 use axum::{
@@ -8,6 +15,7 @@ use axum::{
     routing::get,
     Json, Router,
 };
+use chrono::NaiveDate;
 use redis::{aio::Connection, AsyncCommands};
 use redis_graph::AsyncGraphCommands;
 use serde::Deserialize;
@@ -20,6 +28,11 @@ impl CargoRouter {
             "/cargo/",
             Router::new()
                 .route("/crate/v/:version_id/traverse", get(traverse_version))
+                .route("/crate/v/:version_id/owners", get(version_owners))
+                .route("/crate/v/:version_id/candidates", get(version_candidates))
+                .route("/categories", get(categories))
+                .route("/category/:slug/crates", get(category_crates))
+                .route("/graph/stats", get(graph_stats))
                 .with_state(app_state),
         )
     }
@@ -33,12 +46,55 @@ struct TraverseVersionQueryOptions {
     include_normal_dependencies: Option<bool>,
     include_build_dependencies: Option<bool>,
     include_dev_dependencies: Option<bool>,
+
+    // Walk the lowest matching versions instead of the highest ones
+    minimal_versions: Option<bool>,
+    // YYYY-MM-DD, walk the highest matching versions published on or before that day (UTC)
+    as_of: Option<String>,
 }
 async fn traverse_version(
     Path(id): Path<u32>,
     Query(query): Query<TraverseVersionQueryOptions>,
     State(app_state): State<AppState>,
 ) -> Result<Json<Value>, AppError> {
+    let resolution = dependency_resolution(&query)?;
+    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
+    let traversal = cached_traversal(&mut redis_conn, id, query, resolution).await?;
+
+    Ok(Json(traversal))
+}
+
+// Everyone who can publish code into the traversed tree (takes the same options as traverse_version),
+// ranked by how many of its crates they own
+async fn version_owners(
+    Path(id): Path<u32>,
+    Query(query): Query<TraverseVersionQueryOptions>,
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let resolution = dependency_resolution(&query)?;
+    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
+    let traversal = cached_traversal(&mut redis_conn, id, query, resolution).await?;
+    // Traversal is serialized as [nodes, edges]
+    let version_ids: Vec<i32> = traversal[0]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s["id"].as_i64())
+        .map(|s| s as i32)
+        .collect();
+    let owners = get_tree_owners(&mut redis_conn, &version_ids).await?;
+
+    Ok(Json(json!(owners)))
+}
+
+// Traversals are cached in redis, keyed by the version, every option and when the graph was imported
+// (incremental imports don't flush redis, older traversals simply stop being looked up)
+async fn cached_traversal(
+    redis_conn: &mut Connection,
+    id: u32,
+    query: TraverseVersionQueryOptions,
+    resolution: DependencyResolution,
+) -> anyhow::Result<Value> {
     #[cfg(debug_assertions)]
     let time_to_traverse = std::time::Instant::now();
 
@@ -52,20 +108,20 @@ async fn traverse_version(
     let include_build_dependencies = query.include_build_dependencies.unwrap_or(false);
     let include_dev_dependencies = query.include_dev_dependencies.unwrap_or(false);
     let redis_cache_traversal_key = format!(
-        "{}-{}-{}-{}-{}",
+        "{}-{}-{}-{}-{}-{}-{:?}",
+        import_generation(redis_conn).await?,
         id,
         root_features_raw,
         include_normal_dependencies,
         include_build_dependencies,
-        include_dev_dependencies
+        include_dev_dependencies,
+        resolution
     );
 
-    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
     let cached_result: Option<String> = redis_conn.get(redis_cache_traversal_key.clone()).await?;
-    if cached_result.is_some() {
+    if let Some(cached_result) = cached_result {
         // Parsing takes shit tone of time, fix in future.
-        let parsed_cached_result: serde_json::Value =
-            serde_json::from_str(cached_result.unwrap().as_str())?;
+        let parsed_cached_result: serde_json::Value = serde_json::from_str(cached_result.as_str())?;
 
         #[cfg(debug_assertions)]
         println!(
@@ -73,7 +129,7 @@ async fn traverse_version(
             id,
             time_to_traverse.elapsed()
         );
-        return Ok(Json(parsed_cached_result));
+        return Ok(parsed_cached_result);
     }
 
     let root_node_req = redis_conn
@@ -82,21 +138,25 @@ async fn traverse_version(
             format!("match (cv: CargoCrateVersion {{id: {id}}}) return cv"),
         )
         .await?;
-    let root_node = CargoCrateVersionNode::parse(root_node_req.data.first().unwrap(), "cv")?;
+    let Some(root_node) = root_node_req.data.first() else {
+        return Err(NotFound(format!("Crate version {id} doesn't exist")).into());
+    };
+    let root_node = CargoCrateVersionNode::parse(root_node, "cv")?;
 
     let answ = traverse_tree(
-        &mut redis_conn,
+        redis_conn,
         root_node,
         root_features,
         root_include_default_features,
         include_normal_dependencies,
         include_build_dependencies,
         include_dev_dependencies,
+        resolution,
     )
     .await?;
     let json_answ = json!(answ);
     redis_conn
-        .set(redis_cache_traversal_key, json_answ.to_string())
+        .set::<_, _, ()>(redis_cache_traversal_key, json_answ.to_string())
         .await?;
 
     #[cfg(debug_assertions)]
@@ -105,5 +165,146 @@ async fn traverse_version(
         id,
         time_to_traverse.elapsed()
     );
-    Ok(Json(json_answ))
+    Ok(json_answ)
+}
+
+// Checked before connecting to redis, invalid options are the client's fault
+fn dependency_resolution(
+    query: &TraverseVersionQueryOptions,
+) -> anyhow::Result<DependencyResolution> {
+    let resolution = match (query.minimal_versions.unwrap_or(false), &query.as_of) {
+        (true, Some(_)) => {
+            return Err(
+                BadRequest("minimal_versions and as_of can't be combined".to_owned()).into(),
+            )
+        }
+        (true, None) => DependencyResolution::Minimal,
+        (false, Some(as_of)) => DependencyResolution::AsOf(published_before(as_of)?),
+        (false, None) => DependencyResolution::Maximal,
+    };
+    Ok(resolution)
+}
+
+// Unix timestamp a version has to be published before to count as published on or before `as_of` (YYYY-MM-DD, UTC)
+fn published_before(as_of: &str) -> anyhow::Result<i64> {
+    let day = NaiveDate::parse_from_str(as_of, "%Y-%m-%d")
+        .map_err(|err| BadRequest(format!("Invalid as_of {as_of}: {err}")))?;
+    // Anything published before the next day started
+    let next_day = day
+        .succ_opt()
+        .and_then(|s| s.and_hms_opt(0, 0, 0))
+        .ok_or_else(|| BadRequest(format!("Invalid as_of {as_of}")))?;
+    Ok(next_day.and_utc().timestamp())
+}
+
+// built_at of the last import, 0 when the graph was imported before import stats were stored
+async fn import_generation(redis_conn: &mut Connection) -> anyhow::Result<u64> {
+    let stats: Option<String> = redis_conn.get(IMPORT_STATS_KEY).await?;
+    let Some(stats) = stats else {
+        return Ok(0);
+    };
+    let stats: Value = serde_json::from_str(stats.as_str())?;
+    Ok(stats["built_at"].as_u64().unwrap_or(0))
+}
+
+// Every version each dependency's requirement matches, empty unless the graph was imported with candidate edges
+async fn version_candidates(
+    Path(id): Path<u32>,
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
+    let candidates = get_candidates(&mut redis_conn, id).await?;
+
+    Ok(Json(json!(candidates)))
+}
+
+// Every category with the number of crates in it, slugs are what category_crates takes
+async fn categories(State(app_state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
+    let categories = get_categories(&mut redis_conn).await?;
+
+    Ok(Json(json!(categories)))
+}
+
+// Crates of a category ordered by how many crates depend on them, eg. /category/parser-implementations/crates
+async fn category_crates(
+    Path(slug): Path<String>,
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
+    let crates = get_category_crates(&mut redis_conn, &slug).await?;
+
+    Ok(Json(json!(crates)))
+}
+
+// When and from which dump the graph was built, node / edge counts and what got lost on the way
+async fn graph_stats(State(app_state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let mut redis_conn: Connection = app_state.get_redis_conn().await?;
+    let stats: Option<String> = redis_conn.get(IMPORT_STATS_KEY).await?;
+    let Some(stats) = stats else {
+        return Err(NotFound(
+            "Graph wasn't imported yet (or before import stats were stored)".to_owned(),
+        )
+        .into());
+    };
+
+    Ok(Json(serde_json::from_str(stats.as_str())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse};
+
+    // Never connected to, options are rejected before
+    fn unreachable_state() -> AppState {
+        AppState::new("redis://127.0.0.1:1/".into())
+    }
+
+    fn traverse_query(
+        minimal_versions: Option<bool>,
+        as_of: Option<&str>,
+    ) -> TraverseVersionQueryOptions {
+        TraverseVersionQueryOptions {
+            root_features: None,
+            root_include_default_features: None,
+            include_normal_dependencies: None,
+            include_build_dependencies: None,
+            include_dev_dependencies: None,
+            minimal_versions,
+            as_of: as_of.map(|s| s.to_owned()),
+        }
+    }
+
+    async fn traverse_status(query: TraverseVersionQueryOptions) -> StatusCode {
+        match traverse_version(Path(1), Query(query), State(unreachable_state())).await {
+            Ok(_) => StatusCode::OK,
+            Err(err) => err.into_response().status(),
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_as_of_is_a_bad_request() {
+        assert_eq!(
+            traverse_status(traverse_query(None, Some("01.06.2023"))).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn minimal_versions_as_of_is_a_bad_request() {
+        assert_eq!(
+            traverse_status(traverse_query(Some(true), Some("2023-06-01"))).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn as_of_includes_the_whole_day() {
+        // 2023-06-02T00:00:00Z
+        assert_eq!(published_before("2023-06-01").unwrap(), 1685664000);
+        assert_eq!(published_before("2023-12-31").unwrap(), 1704067200);
+        assert!(published_before("2023-02-30").is_err());
+        assert!(published_before("01.06.2023").is_err());
+    }
 }
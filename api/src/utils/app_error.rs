@@ -6,8 +6,34 @@ use axum::{
 
 pub struct AppError(anyhow::Error);
 
+// Anything asked for that isn't in the graph, answered with 404 instead of 500
+#[derive(Debug)]
+pub struct NotFound(pub String);
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for NotFound {}
+
+// Query options that can't be answered as given, answered with 400 instead of 500
+#[derive(Debug)]
+pub struct BadRequest(pub String);
+impl std::fmt::Display for BadRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for BadRequest {}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(not_found) = self.0.downcast_ref::<NotFound>() {
+            return (StatusCode::NOT_FOUND, not_found.to_string()).into_response();
+        }
+        if let Some(bad_request) = self.0.downcast_ref::<BadRequest>() {
+            return (StatusCode::BAD_REQUEST, bad_request.to_string()).into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
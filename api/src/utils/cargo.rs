@@ -1,17 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap};
 
 use itertools::Itertools;
 use redis::aio::Connection;
-use redis_graph::AsyncGraphCommands;
+use redis_graph::{AsyncGraphCommands, GraphResult};
+use semver::{Version, VersionReq};
+use serde_json::json;
 
 use crate::models::cargo_db_types::{
-    CargoCrateVersionNode, CargoDependencyKind, CargoDependsOnEdge, RedisGraphParser,
+    CargoCanResolveToEdge, CargoCategoryNode, CargoCategoryWithCrateCount, CargoCrateNode,
+    CargoCrateVersionNode, CargoCrateWithReverseDependencies, CargoDependencyCandidates,
+    CargoDependencyKind, CargoDependsOnEdge, CargoFeatureNode, CargoOwnerNode, CargoTreeOwner,
+    RedisGraphParser,
 };
 
 use super::constants::CARGO_GRAPH_NAME;
 
+// Which resolution of the dependencies gets walked, every one is stored as its own edge type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DependencyResolution {
+    // Highest matching versions (DEPENDS_ON)
+    Maximal,
+    // Lowest matching versions (DEPENDS_ON_MIN), only present when imported with minimal versions
+    Minimal,
+    // Highest matching versions published before the unix timestamp, re-resolved from today's DEPENDS_ON edges
+    AsOf(i64),
+}
+impl DependencyResolution {
+    fn edge_type(&self) -> &'static str {
+        match self {
+            DependencyResolution::Maximal | DependencyResolution::AsOf(_) => "DEPENDS_ON",
+            DependencyResolution::Minimal => "DEPENDS_ON_MIN",
+        }
+    }
+}
+
 // TODO: I am fairly certain that there are
 // at least 4 or 5 spots that could be optimized (not using clone, using references, etc)
+#[allow(clippy::too_many_arguments)]
 pub async fn traverse_tree(
     redis_conn: &mut Connection,
 
@@ -22,6 +47,8 @@ pub async fn traverse_tree(
     include_normal_dependencies: bool,
     include_build_dependencies: bool,
     include_dev_dependencies: bool,
+
+    resolution: DependencyResolution,
 ) -> anyhow::Result<(Vec<CargoCrateVersionNode>, Vec<CargoDependsOnEdge>)> {
     let mut wanted_features = root_features;
     if root_include_default_features {
@@ -32,6 +59,8 @@ pub async fn traverse_tree(
     let edge_to_root = CargoDependsOnEdge {
         src_node_id: u64::MAX, // u64::MAX == root
         dest_node_id: root_node.node_id,
+        name: root_node.crate_name.clone(),
+        required_semver: format!("={}", root_node.num),
         optional: false,
         with_features: wanted_features,
         kind: CargoDependencyKind::Normal,
@@ -51,6 +80,7 @@ pub async fn traverse_tree(
             include_normal_dependencies,
             include_build_dependencies,
             include_dev_dependencies,
+            resolution,
         )
         .await?;
 
@@ -73,9 +103,44 @@ pub async fn traverse_tree(
         dbg!(connections_to_traverse.len());
     }
 
+    fill_crate_downloads(redis_conn, &mut traversed_nodes).await?;
+
     Ok((traversed_nodes, traversed_edges))
 }
 
+// Looks up the crates of every traversed version at once, instead of once per traversed node
+async fn fill_crate_downloads(
+    redis_conn: &mut Connection,
+    version_nodes: &mut [CargoCrateVersionNode],
+) -> anyhow::Result<()> {
+    let downloads_result = redis_conn
+        .graph_ro_query(
+            CARGO_GRAPH_NAME,
+            format!(
+                "match (cc:CargoCrate)-[:VERSION]->(cv:CargoCrateVersion) where cv.id in [{}] return cv.id as id, cc.downloads as downloads, cc.recent_downloads as recent_downloads",
+                version_nodes.iter().map(|s| s.id).join(",")
+            ),
+        )
+        .await?;
+    let crate_downloads: HashMap<i32, (Option<i64>, Option<i64>)> = downloads_result
+        .data
+        .iter()
+        .filter_map(|s| {
+            Some((
+                s.get_scalar("id")?,
+                (s.get_scalar("downloads"), s.get_scalar("recent_downloads")),
+            ))
+        })
+        .collect();
+    for version_node in version_nodes.iter_mut() {
+        if let Some((downloads, recent_downloads)) = crate_downloads.get(&version_node.id) {
+            version_node.crate_downloads = *downloads;
+            version_node.crate_recent_downloads = *recent_downloads;
+        }
+    }
+    Ok(())
+}
+
 async fn traverse_node(
     redis_conn: &mut Connection,
 
@@ -85,11 +150,27 @@ async fn traverse_node(
     include_normal_dependencies: bool,
     include_build_dependencies: bool,
     include_dev_dependencies: bool,
+
+    resolution: DependencyResolution,
 ) -> anyhow::Result<Vec<GraphConnection>> {
+    let (dependency_pattern, version_filter) = match resolution {
+        // Today's edge only tells which crate is depended on, every version of it published in time is a candidate
+        DependencyResolution::AsOf(published_before) => (
+            format!(
+                "-[d:{}]->(:CargoCrateVersion)<-[:VERSION]-(:CargoCrate)-[:VERSION]->(cv:CargoCrateVersion)",
+                resolution.edge_type()
+            ),
+            format!(" and cv.created_at < {published_before}"),
+        ),
+        _ => (
+            format!("-[d:{}]->(cv:CargoCrateVersion)", resolution.edge_type()),
+            String::new(),
+        ),
+    };
     let dependencies_query = {
         let mut query = format!(
-            "match (:CargoCrateVersion {{id: {}}})-[d:DEPENDS_ON]->(cv:CargoCrateVersion) where ",
-            root_node.id
+            "match (:CargoCrateVersion {{id: {}}}){} where (",
+            root_node.id, dependency_pattern
         );
         if include_normal_dependencies {
             query.push_str("d.kind = 0 or ");
@@ -101,26 +182,45 @@ async fn traverse_node(
             query.push_str("d.kind = 2 or ");
         }
         query = query.trim_end_matches("or ").to_owned();
-        query.push_str(" return d, cv");
+        query.push(')');
+        query.push_str(&version_filter);
+        query.push_str(" return d, cv, null as f");
+        // Features are only needed (and fetched) when something was asked for, in the same round trip
+        if !wanted_features.is_empty() {
+            query.push_str(&format!(
+                " union all match (:CargoCrateVersion {{id: {}}})-[:HAS_FEATURE]->(f:CargoFeature) return null as d, null as cv, f",
+                root_node.id
+            ));
+        }
 
         query
     };
-    let dependencies_result = redis_conn
+    let (feature_rows, dependency_rows): (Vec<_>, Vec<_>) = redis_conn
         .graph_ro_query(CARGO_GRAPH_NAME, dependencies_query)
-        .await?;
-    let nodes = CargoCrateVersionNode::parse_bulk(&dependencies_result.data, "cv")?;
-    let edges = CargoDependsOnEdge::parse_bulk(&dependencies_result.data, "d")?;
+        .await?
+        .data
+        .into_iter()
+        .partition(|s| s.get_node("f").is_some());
+    let (nodes, edges) = match resolution {
+        DependencyResolution::AsOf(_) => pick_published_versions(&dependency_rows)?,
+        _ => (
+            CargoCrateVersionNode::parse_bulk(&dependency_rows, "cv")?,
+            CargoDependsOnEdge::parse_bulk(&dependency_rows, "d")?,
+        ),
+    };
 
     let mut connections: Vec<GraphConnection> = vec![];
     for edge in edges.iter() {
-        connections.push(GraphConnection {
-            edge: edge.clone(),
-            node: nodes
-                .iter()
-                .find(|s| s.node_id == edge.dest_node_id)
-                .unwrap()
-                .clone(),
-        });
+        let node = nodes
+            .iter()
+            .find(|s| s.node_id == edge.dest_node_id)
+            .unwrap()
+            .clone();
+        let mut edge = edge.clone();
+        if edge.name.is_empty() {
+            edge.name = node.crate_name.clone();
+        }
+        connections.push(GraphConnection { edge, node });
     }
 
     let mut activated_connections: Vec<GraphConnection> = vec![];
@@ -132,9 +232,14 @@ async fn traverse_node(
         }
     }
 
+    let provided_features: HashMap<String, Vec<String>> =
+        CargoFeatureNode::parse_bulk(&feature_rows, "f")?
+            .into_iter()
+            .map(|s| (s.name, s.enables))
+            .collect();
     let mut traversed_features = vec![];
     for wanted_feature in wanted_features {
-        traversed_features.extend(traverse_feature(wanted_feature, &root_node.features));
+        traversed_features.extend(traverse_feature(wanted_feature, &provided_features));
     }
     let filtered_features = traversed_features.iter().unique();
 
@@ -149,7 +254,7 @@ async fn traverse_node(
             feature
         };
         for connection in connections.iter() {
-            if connection.node.crate_name == package_to_activate
+            if connection.edge.name == package_to_activate
                 && !activated_connections
                     .iter()
                     .any(|s| s.node.node_id == connection.node.node_id)
@@ -168,7 +273,7 @@ async fn traverse_node(
         let feature_to_add = package_part_split.next().unwrap();
 
         for connection in connections.iter() {
-            if connection.node.crate_name != package_to_activate {
+            if connection.edge.name != package_to_activate {
                 continue;
             }
 
@@ -196,11 +301,14 @@ async fn traverse_node(
         let feature_to_add = package_part_split.next().unwrap();
 
         for connection in connections.iter() {
-            if connection.node.crate_name != possibly_active_package {
+            if connection.edge.name != possibly_active_package {
                 continue;
             }
 
-            let Some(active_connection) = activated_connections.iter_mut().find(|s| s.node.node_id == connection.node.node_id) else {
+            let Some(active_connection) = activated_connections
+                .iter_mut()
+                .find(|s| s.node.node_id == connection.node.node_id)
+            else {
                 continue;
             };
 
@@ -214,6 +322,212 @@ async fn traverse_node(
     Ok(activated_connections)
 }
 
+// Rows are (today's edge, version published in time) pairs, keeps the best version of every edge.
+// Dependencies without a single matching version didn't exist yet and are dropped.
+fn pick_published_versions(
+    dependencies_data: &[GraphResult],
+) -> anyhow::Result<(Vec<CargoCrateVersionNode>, Vec<CargoDependsOnEdge>)> {
+    let mut candidates_by_edge: HashMap<u64, (CargoDependsOnEdge, Vec<CargoCrateVersionNode>)> =
+        HashMap::new();
+    // Edges in the order the graph returned them, so traversals stay the same between requests
+    let mut edge_ids = vec![];
+    for row in dependencies_data {
+        let edge_id = row
+            .get_relation("d")
+            .ok_or_else(|| anyhow::anyhow!("Dependency row without an edge"))?
+            .id;
+        let node = CargoCrateVersionNode::parse(row, "cv")?;
+        match candidates_by_edge.entry(edge_id) {
+            Entry::Occupied(mut entry) => entry.get_mut().1.push(node),
+            Entry::Vacant(entry) => {
+                edge_ids.push(edge_id);
+                entry.insert((CargoDependsOnEdge::parse(row, "d")?, vec![node]));
+            }
+        }
+    }
+
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    for edge_id in edge_ids {
+        let Some((mut edge, candidates)) = candidates_by_edge.remove(&edge_id) else {
+            continue;
+        };
+        let pick = if edge.required_semver.is_empty() {
+            // Graphs imported before requirements were stored, today's target is kept if it was published in time
+            candidates
+                .into_iter()
+                .find(|s| s.node_id == edge.dest_node_id)
+        } else {
+            pick_best_match(&edge.required_semver, candidates)
+        };
+        let Some(pick) = pick else {
+            continue;
+        };
+        edge.dest_node_id = pick.node_id;
+        nodes.push(pick);
+        edges.push(edge);
+    }
+    Ok((nodes, edges))
+}
+
+// Same preference as the data-preprocessor's resolution: the highest non-yanked stable version,
+// then pre-releases, then yanked versions
+fn pick_best_match(
+    required_semver: &str,
+    candidates: Vec<CargoCrateVersionNode>,
+) -> Option<CargoCrateVersionNode> {
+    let requirement = VersionReq::parse(required_semver).ok()?;
+    candidates
+        .into_iter()
+        .filter_map(|s| {
+            let version = Version::parse(&s.num).ok()?;
+            requirement.matches(&version).then_some((version, s))
+        })
+        .min_by(|(version_a, a), (version_b, b)| {
+            let preference = |version: &Version, node: &CargoCrateVersionNode| {
+                (node.yanked as usize) * 2 + (!version.pre.is_empty() as usize)
+            };
+            preference(version_a, a)
+                .cmp(&preference(version_b, b))
+                .then(version_b.cmp(version_a))
+        })
+        .map(|(_, s)| s)
+}
+
+// Everyone who can publish a new version of a crate in the tree, owning the most crates first
+pub async fn get_tree_owners(
+    redis_conn: &mut Connection,
+    version_ids: &[i32],
+) -> anyhow::Result<Vec<CargoTreeOwner>> {
+    let owners_result = redis_conn
+        .graph_ro_query(
+            CARGO_GRAPH_NAME,
+            format!(
+                "match (cc:CargoCrate)-[:VERSION]->(cv:CargoCrateVersion) where cv.id in [{}] with distinct cc match (owner)-[:OWNS]->(cc) return owner, cc.name as crate_name",
+                version_ids.iter().join(",")
+            ),
+        )
+        .await?;
+
+    let mut owners: HashMap<u64, CargoTreeOwner> = HashMap::new();
+    for row in owners_result.data.iter() {
+        let owner = CargoOwnerNode::parse(row, "owner")?;
+        let crate_name: String = row.get_scalar("crate_name").unwrap_or_default();
+        owners
+            .entry(owner.node_id)
+            .or_insert_with(|| CargoTreeOwner {
+                owner,
+                crates: vec![],
+            })
+            .crates
+            .push(crate_name);
+    }
+
+    Ok(owners
+        .into_values()
+        .map(|mut s| {
+            s.crates.sort();
+            s
+        })
+        .sorted_by(|a, b| {
+            b.crates
+                .len()
+                .cmp(&a.crates.len())
+                .then_with(|| a.owner.login.cmp(&b.owner.login))
+        })
+        .collect())
+}
+
+pub async fn get_categories(
+    redis_conn: &mut Connection,
+) -> anyhow::Result<Vec<CargoCategoryWithCrateCount>> {
+    let categories_result = redis_conn
+        .graph_ro_query(
+            CARGO_GRAPH_NAME,
+            "match (cat:CargoCategory) optional match (cat)<-[:IN_CATEGORY]-(cc:CargoCrate) return cat, count(cc) as crates order by cat.slug",
+        )
+        .await?;
+
+    categories_result
+        .data
+        .iter()
+        .map(|s| {
+            Ok(CargoCategoryWithCrateCount {
+                category: CargoCategoryNode::parse(s, "cat")?,
+                crates: s.get_scalar("crates").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+// Counts reverse dependencies of every version of every crate in the category (any dependency kind),
+// the most depended on crates come first
+pub async fn get_category_crates(
+    redis_conn: &mut Connection,
+    slug: &str,
+) -> anyhow::Result<Vec<CargoCrateWithReverseDependencies>> {
+    let crates_result = redis_conn
+        .graph_ro_query(
+            CARGO_GRAPH_NAME,
+            format!(
+                "match (:CargoCategory {{slug: {}}})<-[:IN_CATEGORY]-(cc:CargoCrate) optional match (cc)-[:VERSION]->(:CargoCrateVersion)<-[d:DEPENDS_ON]-(:CargoCrateVersion)<-[:VERSION]-(dependent:CargoCrate) where dependent <> cc and d.kind <> {} return cc, count(distinct dependent) as reverse_dependencies order by reverse_dependencies desc, cc.name",
+                json!(slug),
+                CargoDependencyKind::Dev as i32
+            ),
+        )
+        .await?;
+
+    crates_result
+        .data
+        .iter()
+        .map(|s| {
+            Ok(CargoCrateWithReverseDependencies {
+                crate_node: CargoCrateNode::parse(s, "cc")?,
+                reverse_dependencies: s.get_scalar("reverse_dependencies").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+// Groups the CAN_RESOLVE_TO edges of a version by the dependency they were created for
+pub async fn get_candidates(
+    redis_conn: &mut Connection,
+    version_id: u32,
+) -> anyhow::Result<Vec<CargoDependencyCandidates>> {
+    let candidates_result = redis_conn
+        .graph_ro_query(
+            CARGO_GRAPH_NAME,
+            format!(
+                "match (:CargoCrateVersion {{id: {version_id}}})-[c:CAN_RESOLVE_TO]->(cv:CargoCrateVersion) return c, cv order by cv.id"
+            ),
+        )
+        .await?;
+    let edges = CargoCanResolveToEdge::parse_bulk(&candidates_result.data, "c")?;
+    let nodes = CargoCrateVersionNode::parse_bulk(&candidates_result.data, "cv")?;
+
+    let mut dependencies: Vec<CargoDependencyCandidates> = vec![];
+    for (edge, node) in edges.into_iter().zip(nodes) {
+        let existing_dependency = dependencies.iter_mut().find(|s| {
+            s.name == edge.name
+                && s.required_semver == edge.required_semver
+                && s.target == edge.target
+                && s.kind == edge.kind
+        });
+        match existing_dependency {
+            Some(dependency) => dependency.versions.push(node),
+            None => dependencies.push(CargoDependencyCandidates {
+                name: edge.name,
+                required_semver: edge.required_semver,
+                target: edge.target,
+                kind: edge.kind,
+                versions: vec![node],
+            }),
+        }
+    }
+
+    Ok(dependencies)
+}
+
 fn traverse_feature(
     wanted_feature: String,
     provided_features: &HashMap<String, Vec<String>>,
@@ -240,3 +554,53 @@ struct GraphConnection {
     edge: CargoDependsOnEdge,
     node: CargoCrateVersionNode,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(node_id: u64, num: &str, yanked: bool) -> CargoCrateVersionNode {
+        CargoCrateVersionNode {
+            node_id,
+            id: node_id as i32,
+            num: num.to_owned(),
+            crate_name: "serde".to_owned(),
+            yanked,
+            description: None,
+            repository: None,
+            documentation: None,
+            homepage: None,
+            created_at: None,
+            downloads: None,
+            recent_downloads: None,
+            crate_downloads: None,
+            crate_recent_downloads: None,
+        }
+    }
+
+    fn pick(required_semver: &str, candidates: &[CargoCrateVersionNode]) -> Option<u64> {
+        pick_best_match(required_semver, candidates.to_vec()).map(|s| s.node_id)
+    }
+
+    #[test]
+    fn best_match_prefers_stable_then_pre_releases_then_yanked() {
+        let candidates = [
+            version(1, "1.0.0", false),
+            version(2, "1.2.0", false),
+            version(3, "1.3.0", true),
+            version(4, "1.4.0-beta.1", false),
+            version(5, "2.0.0", false),
+        ];
+        assert_eq!(pick("^1.0", &candidates), Some(2));
+        assert_eq!(pick("=1.3.0", &candidates), Some(3));
+        assert_eq!(pick(">=1.4.0-beta.1, <2", &candidates), Some(4));
+        assert_eq!(pick("^3", &candidates), None);
+        assert_eq!(pick("not a requirement", &candidates), None);
+    }
+
+    #[test]
+    fn best_match_skips_unparseable_nums() {
+        let candidates = [version(1, "1.0.0", false), version(2, "1.one", false)];
+        assert_eq!(pick("*", &candidates), Some(1));
+    }
+}
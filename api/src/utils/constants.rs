@@ -1 +1,3 @@
 pub const CARGO_GRAPH_NAME: &str = "cargo_graph";
+// Written by the data-preprocessor at the end of every import
+pub const IMPORT_STATS_KEY: &str = "cargo_graph_import_stats";
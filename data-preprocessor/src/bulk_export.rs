@@ -0,0 +1,553 @@
+use std::{collections::HashMap, fs::File, path::Path, sync::Arc};
+
+use serde_json::json;
+
+use crate::{
+    log_debug,
+    models::{CargoDependencyRGEdgeBuilder, CargoOwnerKind},
+    pipeline::{read_recent_downloads, ImportCounts, ImportOptions, IngestionBudget, KeptEntries},
+    source::DataSource,
+    utils::{activated_dependency_names, unix_timestamp, ResolutionStrategy, VersionCache},
+};
+
+// Headers follow the schema enforced format of redisgraph-bulk-loader (https://github.com/RedisGraph/redisgraph-bulk-loader),
+// label / relation type is taken from the file name. Features don't have an id of their own,
+// theirs (`<version id>:<name>`) only exists to link them and isn't stored.
+const CSV_FILES: &[(&str, &[&str])] = &[
+    (
+        "CargoUser",
+        &[
+            "id:ID(CargoUser)",
+            "gh_username:STRING",
+            "gh_avatar:STRING",
+            "preferred_name:STRING",
+        ],
+    ),
+    (
+        "CargoTeam",
+        &[
+            "id:ID(CargoTeam)",
+            "login:STRING",
+            "name:STRING",
+            "avatar:STRING",
+        ],
+    ),
+    (
+        "CargoCrate",
+        &[
+            "id:ID(CargoCrate)",
+            "name:STRING",
+            "description:STRING",
+            "repository:STRING",
+            "documentation:STRING",
+            "homepage:STRING",
+            "downloads:INT",
+            "recent_downloads:INT",
+        ],
+    ),
+    (
+        "CargoCrateVersion",
+        &[
+            "id:ID(CargoCrateVersion)",
+            "num:STRING",
+            "crate_name:STRING",
+            "yanked:BOOLEAN",
+            "description:STRING",
+            "repository:STRING",
+            "documentation:STRING",
+            "homepage:STRING",
+            "created_at:INT",
+            "downloads:INT",
+            "recent_downloads:INT",
+        ],
+    ),
+    (
+        "CargoCategory",
+        &[
+            "id:ID(CargoCategory)",
+            "name:STRING",
+            "slug:STRING",
+            "description:STRING",
+        ],
+    ),
+    ("CargoKeyword", &["id:ID(CargoKeyword)", "name:STRING"]),
+    (
+        "CargoFeature",
+        &[
+            // Running number, features have no id of their own and the loader only takes one id type (`--id-type INTEGER`)
+            ":ID(CargoFeature)",
+            "version_id:INT",
+            "name:STRING",
+            "enables:ARRAY",
+            "activates:ARRAY",
+        ],
+    ),
+    ("OWNS", &[":START_ID(CargoUser)", ":END_ID(CargoCrate)"]),
+    // Loaded as OWNS as well (`-R OWNS OWNS-teams.csv`)
+    (
+        "OWNS-teams",
+        &[":START_ID(CargoTeam)", ":END_ID(CargoCrate)"],
+    ),
+    (
+        "IN_CATEGORY",
+        &[":START_ID(CargoCrate)", ":END_ID(CargoCategory)"],
+    ),
+    (
+        "HAS_KEYWORD",
+        &[":START_ID(CargoCrate)", ":END_ID(CargoKeyword)"],
+    ),
+    (
+        "PUBLISHED",
+        &[":START_ID(CargoUser)", ":END_ID(CargoCrateVersion)"],
+    ),
+    (
+        "VERSION",
+        &[":START_ID(CargoCrate)", ":END_ID(CargoCrateVersion)"],
+    ),
+    (
+        "FIRST_VERSION",
+        &[":START_ID(CargoCrate)", ":END_ID(CargoCrateVersion)"],
+    ),
+    (
+        "LATEST_VERSION",
+        &[":START_ID(CargoCrate)", ":END_ID(CargoCrateVersion)"],
+    ),
+    (
+        "HAS_FEATURE",
+        &[":START_ID(CargoCrateVersion)", ":END_ID(CargoFeature)"],
+    ),
+    (
+        "ENABLES",
+        &[":START_ID(CargoFeature)", ":END_ID(CargoFeature)"],
+    ),
+    // Loaded as ENABLES as well (`-R ENABLES ENABLES-dependencies.csv`)
+    (
+        "ENABLES-dependencies",
+        &[":START_ID(CargoFeature)", ":END_ID(CargoCrateVersion)"],
+    ),
+    ("DEPENDS_ON", DEPENDENCY_CSV_HEADER),
+];
+
+const DEPENDENCY_CSV_HEADER: &[&str] = &[
+    ":START_ID(CargoCrateVersion)",
+    ":END_ID(CargoCrateVersion)",
+    "optional:BOOLEAN",
+    "with_features:ARRAY",
+    "target:STRING",
+    "kind:INT",
+    "name:STRING",
+    "required_semver:STRING",
+];
+
+// Only written with ImportOptions::minimal_versions
+const MINIMAL_DEPENDENCY_CSV_FILE: (&str, &[&str]) = ("DEPENDS_ON_MIN", DEPENDENCY_CSV_HEADER);
+
+// Only written with ImportOptions::as_of_publish_date
+const AS_OF_PUBLISH_DEPENDENCY_CSV_FILE: (&str, &[&str]) =
+    ("DEPENDS_ON_AS_OF_PUBLISH", DEPENDENCY_CSV_HEADER);
+
+// Only written with ImportOptions::candidate_edges
+const CANDIDATE_CSV_FILE: (&str, &[&str]) = (
+    "CAN_RESOLVE_TO",
+    &[
+        ":START_ID(CargoCrateVersion)",
+        ":END_ID(CargoCrateVersion)",
+        "target:STRING",
+        "kind:INT",
+        "name:STRING",
+        "required_semver:STRING",
+    ],
+);
+
+struct CsvFiles {
+    writers: HashMap<&'static str, csv::Writer<File>>,
+}
+impl CsvFiles {
+    fn create<'a>(
+        dir: &Path,
+        csv_files: impl IntoIterator<Item = &'a (&'static str, &'static [&'static str])>,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut writers = HashMap::new();
+        for (name, header) in csv_files {
+            let mut writer = csv::Writer::from_path(dir.join(format!("{name}.csv")))?;
+            writer.write_record(*header)?;
+            writers.insert(*name, writer);
+        }
+        Ok(CsvFiles { writers })
+    }
+
+    fn write(&mut self, name: &str, record: &[String]) -> anyhow::Result<()> {
+        self.writers
+            .get_mut(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown csv file {name}"))?
+            .write_record(record)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        for writer in self.writers.values_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn dependency_record(edge: &CargoDependencyRGEdgeBuilder) -> Vec<String> {
+    vec![
+        edge.from_version_id.to_string(),
+        edge.to_version_id.to_string(),
+        edge.optional.to_string(),
+        json!(edge.with_features).to_string(),
+        optional(&edge.target),
+        edge.kind.to_string(),
+        edge.name.clone(),
+        edge.required_semver.clone(),
+    ]
+}
+
+struct DependencyActivation {
+    feature_id: u64,
+    dependency_name: String,
+    // A feature can activate the same version through multiple dependencies (e.g. different targets)
+    linked_version_ids: Vec<i32>,
+}
+
+// Empty fields aren't set as properties by the bulk loader
+fn optional(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+// Writes the same graph a full import would build as bulk loader csv files into `dir`.
+// Reads the source page by page just like run_import does.
+pub async fn export_bulk_csv(
+    source: &DataSource,
+    budget: &IngestionBudget,
+    options: &ImportOptions,
+    dir: &Path,
+) -> anyhow::Result<ImportCounts> {
+    let optional_csv_files = [
+        options
+            .minimal_versions
+            .then_some(&MINIMAL_DEPENDENCY_CSV_FILE),
+        options
+            .as_of_publish_date
+            .then_some(&AS_OF_PUBLISH_DEPENDENCY_CSV_FILE),
+        options.candidate_edges.then_some(&CANDIDATE_CSV_FILE),
+    ]
+    .into_iter()
+    .flatten();
+    let mut files = CsvFiles::create(dir, CSV_FILES.iter().chain(optional_csv_files))?;
+    let mut counts = ImportCounts::default();
+
+    log_debug!("Exporting users...");
+    let mut users = source.users(budget.page_size);
+    while let Some(page) = users.next_page().await? {
+        counts.users += page.len();
+        for user in page {
+            files.write(
+                "CargoUser",
+                &[
+                    user.id.to_string(),
+                    user.gh_username,
+                    optional(&user.gh_avatar),
+                    optional(&user.preferred_name),
+                ],
+            )?;
+        }
+    }
+
+    log_debug!("Exporting teams...");
+    let mut teams = source.teams(budget.page_size);
+    while let Some(page) = teams.next_page().await? {
+        for team in page {
+            files.write(
+                "CargoTeam",
+                &[
+                    team.id.to_string(),
+                    team.login,
+                    optional(&team.name),
+                    optional(&team.avatar),
+                ],
+            )?;
+        }
+    }
+
+    log_debug!("Reading recent downloads...");
+    let recent_downloads = read_recent_downloads(source, budget.page_size).await?;
+    let mut kept = KeptEntries {
+        recent_downloads: recent_downloads.len(),
+        ..Default::default()
+    };
+
+    // Written once their versions' downloads are summed up
+    log_debug!("Reading crates...");
+    let mut crate_ids = vec![];
+    let mut crates_by_id = HashMap::new();
+    let mut crates = source.crates(budget.page_size_after(&kept));
+    while let Some(page) = crates.next_page().await? {
+        counts.crates += page.len();
+        for crate_row in page {
+            crate_ids.push(crate_row.id);
+            crates_by_id.insert(crate_row.id, crate_row);
+        }
+    }
+    kept.crates = crates_by_id.len();
+    let crates_by_id = Arc::new(crates_by_id);
+
+    log_debug!("Exporting crate owners...");
+    let mut crate_owners = source.crate_owners(budget.page_size_after(&kept));
+    while let Some(page) = crate_owners.next_page().await? {
+        for crate_owner in page {
+            files.write(
+                match crate_owner.owner_kind {
+                    CargoOwnerKind::User => "OWNS",
+                    CargoOwnerKind::Team => "OWNS-teams",
+                },
+                &[
+                    crate_owner.owner_id.to_string(),
+                    crate_owner.crate_id.to_string(),
+                ],
+            )?;
+        }
+    }
+
+    log_debug!("Exporting categories and keywords...");
+    let mut categories = source.categories(budget.page_size_after(&kept));
+    while let Some(page) = categories.next_page().await? {
+        for category in page {
+            files.write(
+                "CargoCategory",
+                &[
+                    category.id.to_string(),
+                    category.name,
+                    category.slug,
+                    category.description,
+                ],
+            )?;
+        }
+    }
+    let mut keywords = source.keywords(budget.page_size_after(&kept));
+    while let Some(page) = keywords.next_page().await? {
+        for keyword in page {
+            files.write("CargoKeyword", &[keyword.id.to_string(), keyword.name])?;
+        }
+    }
+    let mut crate_categories = source.crate_categories(budget.page_size_after(&kept));
+    while let Some(page) = crate_categories.next_page().await? {
+        for crate_category in page {
+            files.write(
+                "IN_CATEGORY",
+                &[
+                    crate_category.crate_id.to_string(),
+                    crate_category.category_id.to_string(),
+                ],
+            )?;
+        }
+    }
+    let mut crate_keywords = source.crate_keywords(budget.page_size_after(&kept));
+    while let Some(page) = crate_keywords.next_page().await? {
+        for crate_keyword in page {
+            files.write(
+                "HAS_KEYWORD",
+                &[
+                    crate_keyword.crate_id.to_string(),
+                    crate_keyword.keyword_id.to_string(),
+                ],
+            )?;
+        }
+    }
+
+    log_debug!("Exporting crate versions and features...");
+    let mut version_cache = VersionCache::default();
+    let mut next_feature_id: u64 = 0;
+    // Features that activate a dependency, linked once dependencies are resolved
+    let mut dependency_activations: HashMap<i32, Vec<DependencyActivation>> = HashMap::new();
+    // Summed up over the crate's versions, see run_import
+    let mut crate_recent_downloads: HashMap<i32, i64> = HashMap::new();
+    let mut crate_versions =
+        source.crate_versions(budget.page_size_after(&kept), crates_by_id.clone());
+    while let Some(page) = crate_versions.next_page().await? {
+        counts.crate_versions += page.len();
+        for version in page {
+            version_cache.insert(&version);
+            kept.cached_versions += 1;
+            let version_recent_downloads = recent_downloads.get(&version.id).copied().unwrap_or(0);
+            *crate_recent_downloads.entry(version.crate_id).or_default() +=
+                version_recent_downloads;
+            files.write(
+                "CargoCrateVersion",
+                &[
+                    version.id.to_string(),
+                    version.num.clone(),
+                    version.crate_name.clone(),
+                    version.yanked.to_string(),
+                    optional(&version.description),
+                    optional(&version.repository),
+                    optional(&version.documentation),
+                    optional(&version.homepage),
+                    unix_timestamp(&version.created_at)
+                        .map(|s| s.to_string())
+                        .unwrap_or_default(),
+                    version.downloads.to_string(),
+                    version_recent_downloads.to_string(),
+                ],
+            )?;
+            if let Some(published_by) = version.published_by {
+                files.write(
+                    "PUBLISHED",
+                    &[published_by.to_string(), version.id.to_string()],
+                )?;
+            }
+            files.write(
+                "VERSION",
+                &[version.crate_id.to_string(), version.id.to_string()],
+            )?;
+
+            let mut feature_names: Vec<&String> = version.features.keys().collect();
+            feature_names.sort();
+            let feature_ids: HashMap<&String, u64> = feature_names
+                .iter()
+                .map(|name| {
+                    next_feature_id += 1;
+                    (*name, next_feature_id)
+                })
+                .collect();
+            for name in feature_names {
+                let feature_id = feature_ids[name];
+                let enables = &version.features[name];
+                let activates = activated_dependency_names(&version.features, enables);
+                files.write(
+                    "CargoFeature",
+                    &[
+                        feature_id.to_string(),
+                        version.id.to_string(),
+                        name.clone(),
+                        json!(enables).to_string(),
+                        json!(activates).to_string(),
+                    ],
+                )?;
+                files.write(
+                    "HAS_FEATURE",
+                    &[version.id.to_string(), feature_id.to_string()],
+                )?;
+                for enabled in enables {
+                    if let Some(enabled_id) = feature_ids.get(enabled) {
+                        files
+                            .write("ENABLES", &[feature_id.to_string(), enabled_id.to_string()])?;
+                    }
+                }
+                if !activates.is_empty() {
+                    kept.dependency_activations += activates.len();
+                    dependency_activations
+                        .entry(version.id)
+                        .or_default()
+                        .extend(activates.into_iter().map(|dependency_name| {
+                            DependencyActivation {
+                                feature_id,
+                                dependency_name: dependency_name.to_owned(),
+                                linked_version_ids: vec![],
+                            }
+                        }));
+                }
+            }
+        }
+    }
+
+    counts.crates_without_parseable_versions = version_cache.crates_without_parseable_versions();
+
+    log_debug!("Exporting crates...");
+    for crate_id in crate_ids {
+        let crate_row = &crates_by_id[&crate_id];
+        let recent_downloads = crate_recent_downloads
+            .get(&crate_id)
+            .copied()
+            .unwrap_or_default();
+        files.write(
+            "CargoCrate",
+            &[
+                crate_row.id.to_string(),
+                crate_row.name.clone(),
+                optional(&crate_row.description),
+                optional(&crate_row.repository),
+                optional(&crate_row.documentation),
+                optional(&crate_row.homepage),
+                crate_row.downloads.to_string(),
+                recent_downloads.to_string(),
+            ],
+        )?;
+    }
+
+    log_debug!("Exporting first and latest versions...");
+    for (latest, file) in [(false, "FIRST_VERSION"), (true, "LATEST_VERSION")] {
+        for (crate_id, version_id) in version_cache.first_or_latest_versions(latest) {
+            files.write(file, &[crate_id.to_string(), version_id.to_string()])?;
+        }
+    }
+
+    log_debug!("Resolving and exporting dependencies...");
+    let mut dependencies = source.raw_dependencies(budget.page_size_after(&kept));
+    while let Some(page) = dependencies.next_page().await? {
+        counts.dependencies += page.len();
+        for dep in page.iter() {
+            if options.candidate_edges {
+                for candidate in version_cache.candidates(dep) {
+                    files.write(
+                        "CAN_RESOLVE_TO",
+                        &[
+                            candidate.from_version_id.to_string(),
+                            candidate.to_version_id.to_string(),
+                            optional(&candidate.target),
+                            candidate.kind.to_string(),
+                            candidate.name,
+                            candidate.required_semver,
+                        ],
+                    )?;
+                }
+            }
+
+            // Unresolved dependencies are reported by the maximal resolution below
+            for strategy in options.resolution_strategies() {
+                if strategy == ResolutionStrategy::Maximal {
+                    continue;
+                }
+                if let Ok(edge) = version_cache.resolve_with(dep, strategy) {
+                    files.write(strategy.edge_type(), &dependency_record(&edge))?;
+                }
+            }
+
+            let Some(edge) = counts
+                .unresolved_dependencies
+                .record(version_cache.resolve(dep))
+            else {
+                continue;
+            };
+            files.write("DEPENDS_ON", &dependency_record(&edge))?;
+
+            let Some(activations) = dependency_activations.get_mut(&edge.from_version_id) else {
+                continue;
+            };
+            for activation in activations
+                .iter_mut()
+                .filter(|s| s.dependency_name == edge.name)
+            {
+                if activation.linked_version_ids.contains(&edge.to_version_id) {
+                    continue;
+                }
+                activation.linked_version_ids.push(edge.to_version_id);
+                files.write(
+                    "ENABLES-dependencies",
+                    &[
+                        activation.feature_id.to_string(),
+                        edge.to_version_id.to_string(),
+                    ],
+                )?;
+            }
+        }
+    }
+
+    files.flush()?;
+    log_debug!("Done exporting.");
+
+    Ok(counts)
+}
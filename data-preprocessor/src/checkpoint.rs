@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use redis::{Commands, Connection};
+
+use crate::{
+    constants::{IMPORT_CHECKPOINT_KEY_SUFFIX, REDIS_INSERTION_CHUNK_SIZE},
+    pipeline::{ImportOptions, IngestionBudget},
+};
+
+// Progress of a full import, kept in redis next to the graph (flushed together with it).
+// Resuming relies on the import generating the same queries in the same order for the same source, budget and options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportCheckpoint {
+    pub graph_name: String,
+    pub source: String,
+    pub budget: IngestionBudget,
+    pub options: ImportOptions,
+    pub executed_queries: usize,
+    seen_queries: usize,
+}
+
+fn checkpoint_key(graph_name: &str) -> String {
+    format!("{graph_name}{IMPORT_CHECKPOINT_KEY_SUFFIX}")
+}
+
+impl ImportCheckpoint {
+    pub fn start(
+        redis_conn: &mut Connection,
+        graph_name: &str,
+        source: String,
+        budget: &IngestionBudget,
+        options: &ImportOptions,
+    ) -> anyhow::Result<Self> {
+        redis_conn.hset_multiple::<_, _, _, ()>(
+            checkpoint_key(graph_name),
+            &[
+                ("source", source.clone()),
+                ("page_size", budget.page_size.to_string()),
+                ("chunk_size", budget.chunk_size.to_string()),
+                ("memory_bytes", budget.memory_bytes.to_string()),
+                ("options", serde_json::to_string(options)?),
+                ("executed_queries", "0".to_owned()),
+            ],
+        )?;
+
+        Ok(ImportCheckpoint {
+            graph_name: graph_name.to_owned(),
+            source,
+            budget: budget.clone(),
+            options: options.clone(),
+            executed_queries: 0,
+            seen_queries: 0,
+        })
+    }
+
+    pub fn load(redis_conn: &mut Connection, graph_name: &str) -> anyhow::Result<Option<Self>> {
+        let fields: HashMap<String, String> = redis_conn.hgetall(checkpoint_key(graph_name))?;
+        let (Some(source), Some(page_size), Some(executed_queries)) = (
+            fields.get("source"),
+            fields.get("page_size"),
+            fields.get("executed_queries"),
+        ) else {
+            return Ok(None);
+        };
+
+        Ok(Some(ImportCheckpoint {
+            graph_name: graph_name.to_owned(),
+            source: source.clone(),
+            budget: IngestionBudget {
+                page_size: page_size.parse()?,
+                // Checkpoints written before the chunk size was configurable
+                chunk_size: match fields.get("chunk_size") {
+                    Some(chunk_size) => chunk_size.parse()?,
+                    None => REDIS_INSERTION_CHUNK_SIZE,
+                },
+                // Checkpoints written before kept lookups shrank the pages, which never happens with an unbounded budget
+                memory_bytes: match fields.get("memory_bytes") {
+                    Some(memory_bytes) => memory_bytes.parse()?,
+                    None => usize::MAX,
+                },
+            },
+            // Checkpoints written before there were any options
+            options: match fields.get("options") {
+                Some(options) => serde_json::from_str(options)?,
+                None => ImportOptions::default(),
+            },
+            executed_queries: executed_queries.parse()?,
+            seen_queries: 0,
+        }))
+    }
+
+    // Queries executed by a previous run are skipped. The query and the checkpoint update are sent
+    // as a single transaction, so a crash can't leave a query executed but not recorded (or vice versa).
+    pub fn execute(&mut self, redis_conn: &mut Connection, query: &str) -> anyhow::Result<()> {
+        self.seen_queries += 1;
+        if self.seen_queries <= self.executed_queries {
+            return Ok(());
+        }
+
+        let transaction_result = redis::pipe()
+            .atomic()
+            .cmd("GRAPH.QUERY")
+            .arg(&self.graph_name)
+            .arg(query)
+            .arg("--compact")
+            .ignore()
+            .hset(
+                checkpoint_key(&self.graph_name),
+                "executed_queries",
+                self.seen_queries,
+            )
+            .ignore()
+            .query::<()>(redis_conn);
+        if let Err(err) = transaction_result {
+            // Redis doesn't roll back the rest of a transaction when one of its commands fails
+            redis_conn.hset::<_, _, _, ()>(
+                checkpoint_key(&self.graph_name),
+                "executed_queries",
+                self.executed_queries,
+            )?;
+            return Err(err.into());
+        }
+        self.executed_queries = self.seen_queries;
+
+        Ok(())
+    }
+
+    pub fn finish(self, redis_conn: &mut Connection) -> anyhow::Result<()> {
+        redis_conn.del::<_, ()>(checkpoint_key(&self.graph_name))?;
+        Ok(())
+    }
+}
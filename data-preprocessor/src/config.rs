@@ -0,0 +1,203 @@
+use std::{path::Path, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{
+    constants::{
+        CARGO_GRAPH_NAME, DEFAULT_CONFIG_FILE, DEFAULT_GRAPH_URL, DEFAULT_MEMORY_BUDGET_MB,
+        DEFAULT_POSTGRES_POOL_SIZE, DEFAULT_POSTGRES_URL, DEFAULT_WARM_UP_CONCURRENCY,
+        REDIS_INSERTION_CHUNK_SIZE,
+    },
+    pipeline::{ImportOptions, IngestionBudget},
+};
+
+// Prefix of the environment variables overriding the config file, eg. `DEPS_GRAPH_GRAPH_URL`
+const ENV_PREFIX: &str = "DEPS_GRAPH_";
+
+// Everything missing from the config file falls back to the defaults used for local development (see README)
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub postgres: PostgresConfig,
+    pub graph: GraphConfig,
+    pub import: ImportConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostgresConfig {
+    pub url: String,
+    pub pool_size: u32,
+}
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        PostgresConfig {
+            url: DEFAULT_POSTGRES_URL.to_owned(),
+            pool_size: DEFAULT_POSTGRES_POOL_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphConfig {
+    pub url: String,
+    pub name: String,
+    pub sink: SinkKind,
+}
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            url: DEFAULT_GRAPH_URL.to_owned(),
+            name: CARGO_GRAPH_NAME.to_owned(),
+            sink: SinkKind::default(),
+        }
+    }
+}
+
+// Where imports write their queries to. RedisGraph and FalkorDB are both written to through
+// GraphDatabaseSink at graph.url (FalkorDB kept RedisGraph's commands), files through CypherFilesSink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    #[default]
+    RedisGraph,
+    FalkorDb,
+    Files,
+}
+impl FromStr for SinkKind {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "redisgraph" => Ok(SinkKind::RedisGraph),
+            "falkordb" => Ok(SinkKind::FalkorDb),
+            "files" => Ok(SinkKind::Files),
+            _ => Err(format!(
+                "unknown sink {raw}, expected redisgraph, falkordb or files"
+            )),
+        }
+    }
+}
+impl std::fmt::Display for SinkKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SinkKind::RedisGraph => write!(f, "RedisGraph"),
+            SinkKind::FalkorDb => write!(f, "FalkorDB"),
+            SinkKind::Files => write!(f, "cypher files"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImportConfig {
+    pub chunk_size: usize,
+    pub memory_budget_mb: usize,
+    pub warm_up_concurrency: usize,
+    // See ImportOptions
+    pub candidate_edges: bool,
+    pub minimal_versions: bool,
+    pub as_of_publish_date: bool,
+}
+impl Default for ImportConfig {
+    fn default() -> Self {
+        ImportConfig {
+            chunk_size: REDIS_INSERTION_CHUNK_SIZE,
+            memory_budget_mb: DEFAULT_MEMORY_BUDGET_MB,
+            warm_up_concurrency: DEFAULT_WARM_UP_CONCURRENCY,
+            candidate_edges: false,
+            minimal_versions: false,
+            as_of_publish_date: false,
+        }
+    }
+}
+impl ImportConfig {
+    pub fn budget(&self) -> IngestionBudget {
+        IngestionBudget::from_megabytes(self.memory_budget_mb, self.chunk_size)
+    }
+
+    pub fn options(&self) -> ImportOptions {
+        ImportOptions {
+            candidate_edges: self.candidate_edges,
+            minimal_versions: self.minimal_versions,
+            as_of_publish_date: self.as_of_publish_date,
+        }
+    }
+}
+
+impl Config {
+    // An explicitly given file has to exist, the default one is optional.
+    // Environment variables are applied on top of the file, call validate once every override is applied.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let mut config = match path {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env_overrides(std::env::vars())?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let raw_config = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Unable to read config {}: {}", path.display(), err))?;
+        toml::from_str(&raw_config)
+            .map_err(|err| anyhow::anyhow!("Invalid config {}: {}", path.display(), err))
+    }
+
+    // Variables without the DEPS_GRAPH_ prefix are ignored, unknown ones with it are an error (most likely a typo)
+    pub fn apply_env_overrides<I>(&mut self, vars: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (key, value) in vars {
+            let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            match name {
+                "POSTGRES_URL" => self.postgres.url = value,
+                "POSTGRES_POOL_SIZE" => self.postgres.pool_size = parse_env(&key, &value)?,
+                "GRAPH_URL" => self.graph.url = value,
+                "GRAPH_NAME" => self.graph.name = value,
+                "GRAPH_SINK" => self.graph.sink = parse_env(&key, &value)?,
+                "CHUNK_SIZE" => self.import.chunk_size = parse_env(&key, &value)?,
+                "MEMORY_BUDGET_MB" => self.import.memory_budget_mb = parse_env(&key, &value)?,
+                "CANDIDATE_EDGES" => self.import.candidate_edges = parse_env(&key, &value)?,
+                "MINIMAL_VERSIONS" => self.import.minimal_versions = parse_env(&key, &value)?,
+                "AS_OF_PUBLISH_DATE" => self.import.as_of_publish_date = parse_env(&key, &value)?,
+                "WARM_UP_CONCURRENCY" => self.import.warm_up_concurrency = parse_env(&key, &value)?,
+                _ => anyhow::bail!("Unknown environment variable {key}"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.graph.name.is_empty() {
+            anyhow::bail!("graph.name can't be empty");
+        }
+        if self.postgres.pool_size == 0 {
+            anyhow::bail!("postgres.pool_size has to be at least 1");
+        }
+        if self.import.chunk_size == 0 {
+            anyhow::bail!("import.chunk_size has to be at least 1");
+        }
+        if self.import.warm_up_concurrency == 0 {
+            anyhow::bail!("import.warm_up_concurrency has to be at least 1");
+        }
+        Ok(())
+    }
+}
+
+fn parse_env<T>(key: &str, value: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Invalid {key}={value}: {err}"))
+}
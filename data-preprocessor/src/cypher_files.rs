@@ -0,0 +1,146 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const CYPHER_FILE_EXTENSION: &str = "cypher";
+
+// Describes a dry-run, queries have to be replayed in the listed order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CypherManifest {
+    // Full imports are replayed into a clean graph, incremental ones on top of the graph they were generated against
+    pub full_import: bool,
+    pub source: String,
+    pub page_size: usize,
+    pub queries: Vec<CypherManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CypherManifestEntry {
+    pub file: String,
+    pub rows: usize,
+}
+
+pub struct CypherFileWriter {
+    dir: PathBuf,
+    manifest: CypherManifest,
+}
+
+impl CypherFileWriter {
+    // Files of a previous dry-run in the same directory are removed, so they can't get mixed up
+    pub fn create(
+        dir: &Path,
+        full_import: bool,
+        source: String,
+        page_size: usize,
+    ) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_previous_output = path.extension().and_then(|s| s.to_str())
+                == Some(CYPHER_FILE_EXTENSION)
+                || path.file_name().and_then(|s| s.to_str()) == Some(MANIFEST_FILE_NAME);
+            if is_previous_output {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(CypherFileWriter {
+            dir: dir.to_path_buf(),
+            manifest: CypherManifest {
+                full_import,
+                source,
+                page_size,
+                queries: vec![],
+            },
+        })
+    }
+
+    pub fn write(&mut self, query: &str) -> anyhow::Result<()> {
+        let file = format!(
+            "{:06}.{CYPHER_FILE_EXTENSION}",
+            self.manifest.queries.len() + 1
+        );
+        fs::write(self.dir.join(&file), query)?;
+        self.manifest.queries.push(CypherManifestEntry {
+            file,
+            rows: count_unwind_rows(query),
+        });
+        Ok(())
+    }
+
+    // Manifest is written last, a dry-run that didn't finish can't be replayed
+    pub fn finish(self) -> anyhow::Result<CypherManifest> {
+        fs::write(
+            self.dir.join(MANIFEST_FILE_NAME),
+            serde_json::to_string_pretty(&self.manifest)?,
+        )?;
+        Ok(self.manifest)
+    }
+}
+
+pub fn read_cypher_manifest(dir: &Path) -> anyhow::Result<CypherManifest> {
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    let raw_manifest = fs::read_to_string(&manifest_path).map_err(|err| {
+        anyhow::anyhow!(
+            "Unable to read {} (did the dry-run finish?): {}",
+            manifest_path.display(),
+            err
+        )
+    })?;
+    Ok(serde_json::from_str(&raw_manifest)?)
+}
+
+// Reads the files one by one, in the manifest's order
+pub fn replay_cypher_files<F>(
+    dir: &Path,
+    manifest: &CypherManifest,
+    mut execute: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(String) -> anyhow::Result<()>,
+{
+    for entry in manifest.queries.iter() {
+        execute(fs::read_to_string(dir.join(&entry.file))?)?;
+    }
+    Ok(())
+}
+
+// Generated queries look like `unwind [row, row, ...] as map ...`, rows are separated by top level commas
+pub fn count_unwind_rows(query: &str) -> usize {
+    let Some(rows) = query.strip_prefix("unwind [") else {
+        return 0;
+    };
+
+    let mut row_count = 0;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in rows.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => depth += 1,
+            ']' | '}' if depth == 0 => break,
+            ']' | '}' => depth -= 1,
+            ',' if depth == 0 => row_count += 1,
+            _ => {}
+        }
+        if row_count == 0 && !c.is_whitespace() {
+            row_count = 1;
+        }
+    }
+
+    row_count
+}
@@ -0,0 +1,416 @@
+use std::{collections::HashMap, fs::File, path::Path, time::Instant};
+
+use chrono::NaiveDateTime;
+use flate2::read::GzDecoder;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use crate::{
+    log_debug,
+    models::{
+        CargoCategoryDBResponse, CargoCrateCategoryDBResponse, CargoCrateDBResponse,
+        CargoCrateKeywordDBResponse, CargoCrateOwnerDBResponse, CargoCrateVersionDBResponse,
+        CargoDependenciesDBResponse, CargoDependencyKind, CargoKeywordDBResponse, CargoOwnerKind,
+        CargoTeamDBResponse, CargoUserDBResponse, CargoVersionDownloadsDBResponse,
+    },
+};
+
+// Raw rows, as exported by crates.io (https://static.crates.io/db-dump.tar.gz).
+// Columns not listed here are ignored by the csv deserializer.
+#[derive(Debug, Deserialize)]
+struct DumpUserRow {
+    id: i32,
+    gh_login: String,
+    gh_avatar: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpTeamRow {
+    id: i32,
+    login: String,
+    name: Option<String>,
+    avatar: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpCrateRow {
+    id: i32,
+    name: String,
+    description: Option<String>,
+    repository: Option<String>,
+    documentation: Option<String>,
+    homepage: Option<String>,
+    downloads: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpCategoryRow {
+    id: i32,
+    category: String,
+    slug: String,
+    description: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpKeywordRow {
+    id: i32,
+    keyword: String,
+}
+
+// Deleted ownerships aren't part of the dump
+#[derive(Debug, Deserialize)]
+struct DumpCrateOwnerRow {
+    crate_id: i32,
+    owner_id: i32,
+    owner_kind: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpCrateVersionRow {
+    id: i32,
+    crate_id: i32,
+    num: String,
+    features: String,
+    published_by: Option<i32>,
+    #[serde(deserialize_with = "deserialize_pg_bool")]
+    yanked: bool,
+    #[serde(default, deserialize_with = "deserialize_pg_timestamp")]
+    created_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    downloads: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpVersionDownloadsRow {
+    version_id: i32,
+    downloads: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DumpDependencyRow {
+    version_id: i32,
+    crate_id: i32,
+    explicit_name: Option<String>,
+    req: String,
+    #[serde(deserialize_with = "deserialize_pg_bool")]
+    optional: bool,
+    #[serde(deserialize_with = "deserialize_pg_bool")]
+    default_features: bool,
+    #[serde(deserialize_with = "deserialize_pg_array")]
+    features: Vec<String>,
+    target: Option<String>,
+    kind: i32,
+}
+
+pub fn read_users_from_dump(
+    dump_path: &Path,
+    mut on_user: impl FnMut(CargoUserDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "users", |row: DumpUserRow| {
+        on_user(CargoUserDBResponse {
+            id: row.id,
+            gh_username: row.gh_login,
+            gh_avatar: row.gh_avatar,
+            preferred_name: row.name,
+        })
+    })
+}
+
+pub fn read_teams_from_dump(
+    dump_path: &Path,
+    mut on_team: impl FnMut(CargoTeamDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "teams", |row: DumpTeamRow| {
+        on_team(CargoTeamDBResponse {
+            id: row.id,
+            login: row.login,
+            name: row.name,
+            avatar: row.avatar,
+        })
+    })
+}
+
+pub fn read_crates_from_dump(
+    dump_path: &Path,
+    mut on_crate: impl FnMut(CargoCrateDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "crates", |row: DumpCrateRow| {
+        on_crate(CargoCrateDBResponse {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            repository: row.repository,
+            documentation: row.documentation,
+            homepage: row.homepage,
+            downloads: row.downloads,
+        })
+    })
+}
+
+pub fn read_categories_from_dump(
+    dump_path: &Path,
+    mut on_category: impl FnMut(CargoCategoryDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "categories", |row: DumpCategoryRow| {
+        on_category(CargoCategoryDBResponse {
+            id: row.id,
+            name: row.category,
+            slug: row.slug,
+            description: row.description,
+        })
+    })
+}
+
+pub fn read_keywords_from_dump(
+    dump_path: &Path,
+    mut on_keyword: impl FnMut(CargoKeywordDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "keywords", |row: DumpKeywordRow| {
+        on_keyword(CargoKeywordDBResponse {
+            id: row.id,
+            name: row.keyword,
+        })
+    })
+}
+
+// Join tables already have the model's column names
+pub fn read_crate_categories_from_dump(
+    dump_path: &Path,
+    on_crate_category: impl FnMut(CargoCrateCategoryDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "crates_categories", on_crate_category)
+}
+
+pub fn read_crate_keywords_from_dump(
+    dump_path: &Path,
+    on_crate_keyword: impl FnMut(CargoCrateKeywordDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "crates_keywords", on_crate_keyword)
+}
+
+pub fn read_crate_owners_from_dump(
+    dump_path: &Path,
+    mut on_crate_owner: impl FnMut(CargoCrateOwnerDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "crate_owners", |row: DumpCrateOwnerRow| {
+        on_crate_owner(CargoCrateOwnerDBResponse {
+            crate_id: row.crate_id,
+            owner_id: row.owner_id,
+            owner_kind: CargoOwnerKind::try_from(row.owner_kind)?,
+        })
+    })
+}
+
+// Rows are in the dump's order, not ordered by id.
+pub fn read_crate_versions_from_dump(
+    dump_path: &Path,
+    crates: &HashMap<i32, CargoCrateDBResponse>,
+    mut on_crate_version: impl FnMut(CargoCrateVersionDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "versions", |row: DumpCrateVersionRow| {
+        let crate_row = crates.get(&row.crate_id);
+        on_crate_version(CargoCrateVersionDBResponse {
+            id: row.id,
+            crate_id: row.crate_id,
+            num: row.num,
+            features: sqlx::types::Json(serde_json::from_str(&row.features)?),
+            published_by: row.published_by,
+            yanked: row.yanked,
+            created_at: row.created_at,
+            downloads: row.downloads,
+            crate_name: crate_row.map(|s| s.name.clone()).unwrap_or_default(),
+            description: crate_row.and_then(|s| s.description.clone()),
+            repository: crate_row.and_then(|s| s.repository.clone()),
+            documentation: crate_row.and_then(|s| s.documentation.clone()),
+            homepage: crate_row.and_then(|s| s.homepage.clone()),
+        })
+    })
+}
+
+pub fn read_raw_dependencies_from_dump(
+    dump_path: &Path,
+    mut on_dependency: impl FnMut(CargoDependenciesDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(dump_path, "dependencies", |row: DumpDependencyRow| {
+        on_dependency(CargoDependenciesDBResponse {
+            from_version_id: row.version_id,
+            to_crate_id: row.crate_id,
+            explicit_name: row.explicit_name,
+            required_semver: row.req,
+            optional: row.optional,
+            default_features: row.default_features,
+            features: row.features,
+            target: row.target,
+            kind: CargoDependencyKind::try_from(row.kind)?,
+        })
+    })
+}
+
+// One row per version and day
+pub fn read_version_downloads_from_dump(
+    dump_path: &Path,
+    mut on_version_downloads: impl FnMut(CargoVersionDownloadsDBResponse) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    read_dump_table(
+        dump_path,
+        "version_downloads",
+        |row: DumpVersionDownloadsRow| {
+            on_version_downloads(CargoVersionDownloadsDBResponse {
+                version_id: row.version_id,
+                downloads: row.downloads,
+            })
+        },
+    )
+}
+
+// Tables the import reads
+pub const DUMP_TABLES: &[&str] = &[
+    "users",
+    "teams",
+    "crates",
+    "crate_owners",
+    "categories",
+    "crates_categories",
+    "keywords",
+    "crates_keywords",
+    "versions",
+    "version_downloads",
+    "dependencies",
+];
+
+// Only looks at the entry names, still has to decompress the whole archive
+pub fn missing_dump_tables(dump_path: &Path) -> anyhow::Result<Vec<&'static str>> {
+    let mut missing_tables = DUMP_TABLES.to_vec();
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(dump_path)?));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let entry_path = entry.path()?;
+        if entry_path
+            .parent()
+            .and_then(|s| s.file_name())
+            .and_then(|s| s.to_str())
+            != Some("data")
+        {
+            continue;
+        }
+        let Some(table) = entry_path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.strip_suffix(".csv"))
+        else {
+            continue;
+        };
+        missing_tables.retain(|s| *s != table);
+    }
+
+    Ok(missing_tables)
+}
+
+// Streams `<dump root>/data/<table>.csv` out of the tarball without extracting it to disk.
+// Every call decompresses the archive from the start, so tables can be read in parallel. That's one pass
+// per table (and tables are read in import order, not archive order), the time it takes is logged.
+pub fn read_dump_table<T, F>(dump_path: &Path, table: &str, mut on_row: F) -> anyhow::Result<()>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> anyhow::Result<()>,
+{
+    let wanted_file_name = format!("{table}.csv");
+    let started_at = Instant::now();
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(dump_path)?));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        let is_wanted_table = entry_path.file_name().and_then(|s| s.to_str())
+            == Some(wanted_file_name.as_str())
+            && entry_path
+                .parent()
+                .and_then(|s| s.file_name())
+                .and_then(|s| s.to_str())
+                == Some("data");
+        if !is_wanted_table {
+            continue;
+        }
+
+        log_debug!(
+            "Decompressed up to data/{} in {:.1?}",
+            wanted_file_name,
+            started_at.elapsed()
+        );
+        let mut csv_reader = csv::Reader::from_reader(entry);
+        for row in csv_reader.deserialize::<T>() {
+            on_row(row?)?;
+        }
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} does not contain data/{}",
+        dump_path.display(),
+        wanted_file_name
+    )
+}
+
+// Postgres' COPY exports booleans as `t` / `f`
+fn deserialize_pg_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    match raw.as_str() {
+        "t" | "true" => Ok(true),
+        "f" | "false" => Ok(false),
+        _ => Err(serde::de::Error::custom(format!(
+            "invalid postgres boolean: {raw}"
+        ))),
+    }
+}
+
+// Postgres' COPY exports timestamp as `2017-04-20 18:45:28.474245` (fraction omitted when zero)
+fn deserialize_pg_timestamp<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    NaiveDateTime::parse_from_str(&raw, "%Y-%m-%d %H:%M:%S%.f")
+        .map(Some)
+        .map_err(|err| serde::de::Error::custom(format!("invalid postgres timestamp {raw}: {err}")))
+}
+
+fn deserialize_pg_array<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    Ok(parse_pg_array(&raw))
+}
+
+// Postgres' COPY exports text[] as `{a,b,"c d"}`
+pub fn parse_pg_array(raw: &str) -> Vec<String> {
+    let inner = raw.trim().trim_start_matches('{').trim_end_matches('}');
+    if inner.is_empty() {
+        return vec![];
+    }
+
+    let mut items = vec![];
+    let mut current_item = String::new();
+    let mut in_quotes = false;
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\\' if in_quotes => {
+                if let Some(escaped) = chars.next() {
+                    current_item.push(escaped);
+                }
+            }
+            ',' if !in_quotes => items.push(std::mem::take(&mut current_item)),
+            _ => current_item.push(c),
+        }
+    }
+    items.push(current_item);
+
+    items
+}
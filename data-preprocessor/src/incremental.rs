@@ -0,0 +1,214 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use itertools::Itertools;
+
+use crate::{
+    models::{
+        CargoCandidateRGEdgeBuilder, CargoDependenciesDBResponse, CargoDependencyRGEdgeBuilder,
+    },
+    unresolved::UnresolvedDependencyReport,
+    utils::{ResolutionStrategy, VersionCache},
+};
+
+// Ids of the nodes (and links between them) that are already present in the graph.
+// Nodes the source doesn't list anymore are deleted, together with their links.
+#[derive(Debug, Default)]
+pub struct ExistingGraphIds {
+    pub users: HashSet<i32>,
+    pub teams: HashSet<i32>,
+    pub crates: HashSet<i32>,
+    pub categories: HashSet<i32>,
+    pub keywords: HashSet<i32>,
+    pub crate_versions: HashSet<i32>,
+    // Number of versions linked to every crate, tells which crates lost versions
+    pub crate_version_counts: HashMap<i32, usize>,
+    // Subset of crate_versions that are marked as yanked in the graph
+    pub yanked_crate_versions: HashSet<i32>,
+    // (owner_id, crate_id) pairs of OWNS links, by the kind of owner
+    pub user_owners: HashSet<(i32, i32)>,
+    pub team_owners: HashSet<(i32, i32)>,
+    // (crate_id, category_id) and (crate_id, keyword_id) pairs
+    pub crate_categories: HashSet<(i32, i32)>,
+    pub crate_keywords: HashSet<(i32, i32)>,
+    // See crate_metadata_hash, by crate id
+    pub crate_metadata: HashMap<i32, u64>,
+    // (downloads, recent_downloads) by crate and crate version id
+    pub crate_downloads: HashMap<i32, (i64, i64)>,
+    pub crate_version_downloads: HashMap<i32, (i64, i64)>,
+}
+
+// Downloads change every day but not for every crate (version), unchanged ones aren't set again
+pub fn downloads_changed(
+    existing_downloads: Option<&HashMap<i32, (i64, i64)>>,
+    id: i32,
+    downloads: (i64, i64),
+) -> bool {
+    existing_downloads.is_none_or(|s| s.get(&id) != Some(&downloads))
+}
+
+// Crate metadata (description, repository, documentation, homepage) is only compared, so a hash of it is enough
+pub fn crate_metadata_hash(metadata: [Option<&str>; 4]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    metadata.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Node ids or links the source lists in full on every import (every node, owners, categories and keywords of a crate).
+// Everything the source still lists is checked off, whatever is left over isn't listed anymore.
+pub struct SourceDelta<'a, T> {
+    existing: &'a HashSet<T>,
+    unseen: HashSet<T>,
+}
+impl<'a, T: Copy + Eq + Hash + Ord> SourceDelta<'a, T> {
+    pub fn new(existing: &'a HashSet<T>) -> Self {
+        SourceDelta {
+            existing,
+            unseen: existing.clone(),
+        }
+    }
+
+    // True if the node / link isn't in the graph yet
+    pub fn check_off(&mut self, listed: T) -> bool {
+        self.unseen.remove(&listed);
+        !self.existing.contains(&listed)
+    }
+
+    // Sorted, so the removal queries are the same on every run
+    pub fn removed(self) -> Vec<T> {
+        self.unseen.into_iter().sorted().collect()
+    }
+}
+
+// Crates whose graph versions aren't all listed anymore. Their deleted versions took the DEPENDS_ON,
+// FIRST_VERSION and LATEST_VERSION edges pointing at them along, so edges to these crates are relinked.
+pub fn crates_with_removed_versions(
+    existing_version_counts: &HashMap<i32, usize>,
+    listed_version_counts: &HashMap<i32, usize>,
+) -> HashSet<i32> {
+    existing_version_counts
+        .iter()
+        .filter(|(crate_id, count)| listed_version_counts.get(crate_id).unwrap_or(&0) < count)
+        .map(|(crate_id, _)| *crate_id)
+        .collect()
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DependencyEdgesDelta {
+    pub removed: Vec<CargoDependencyRGEdgeBuilder>,
+    pub added: Vec<CargoDependencyRGEdgeBuilder>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CandidateEdgesDelta {
+    pub removed: Vec<CargoCandidateRGEdgeBuilder>,
+    pub added: Vec<CargoCandidateRGEdgeBuilder>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FirstOrLatestVersionDelta {
+    // Crates whose current link has to be removed before the new one is created
+    pub removed_crate_ids: Vec<i32>,
+    // (crate_id, version_id) pairs
+    pub added: Vec<(i32, i32)>,
+}
+
+// Crate versions are immutable once published, so only two things can change an existing edge:
+// a dependency was added (new version) or the target crate got a new version that matches better.
+// Resolving every dependency against the graph's version set and against the new one tells both apart.
+// Only the resolution against the new version set ends up in `report`.
+// Dependencies on `relinked_crate_ids` (see crates_with_removed_versions) are removed and added again either way.
+pub fn diff_dependency_edges(
+    old_version_cache: &VersionCache,
+    new_version_cache: &VersionCache,
+    existing_version_ids: &HashSet<i32>,
+    relinked_crate_ids: &HashSet<i32>,
+    db_dependencies: &[CargoDependenciesDBResponse],
+    strategy: ResolutionStrategy,
+    report: &mut UnresolvedDependencyReport,
+) -> DependencyEdgesDelta {
+    let mut delta = DependencyEdgesDelta::default();
+    for dep in db_dependencies {
+        let old_edge = if existing_version_ids.contains(&dep.from_version_id) {
+            old_version_cache.resolve_with(dep, strategy).ok()
+        } else {
+            None
+        };
+        let new_edge = report.record(new_version_cache.resolve_with(dep, strategy));
+        if old_edge == new_edge && !relinked_crate_ids.contains(&dep.to_crate_id) {
+            continue;
+        }
+
+        delta.removed.extend(old_edge);
+        delta.added.extend(new_edge);
+    }
+
+    delta
+}
+
+// Same as with dependency edges, except that every matching version is linked.
+// Mostly newly published versions matching existing requirements.
+pub fn diff_candidate_edges(
+    old_version_cache: &VersionCache,
+    new_version_cache: &VersionCache,
+    existing_version_ids: &HashSet<i32>,
+    db_dependencies: &[CargoDependenciesDBResponse],
+) -> CandidateEdgesDelta {
+    let mut delta = CandidateEdgesDelta::default();
+    for dep in db_dependencies {
+        let old_candidates = if existing_version_ids.contains(&dep.from_version_id) {
+            old_version_cache.candidates(dep)
+        } else {
+            vec![]
+        };
+        let new_candidates = new_version_cache.candidates(dep);
+
+        let old_candidate_set: HashSet<&CargoCandidateRGEdgeBuilder> =
+            old_candidates.iter().collect();
+        let new_candidate_set: HashSet<&CargoCandidateRGEdgeBuilder> =
+            new_candidates.iter().collect();
+        delta.removed.extend(
+            old_candidates
+                .iter()
+                .filter(|s| !new_candidate_set.contains(s))
+                .cloned(),
+        );
+        delta.added.extend(
+            new_candidates
+                .iter()
+                .filter(|s| !old_candidate_set.contains(s))
+                .cloned(),
+        );
+    }
+
+    delta
+}
+
+// Links of `relinked_crate_ids` (see crates_with_removed_versions) are removed and added again either way
+pub fn diff_first_or_latest_versions(
+    old_version_cache: &VersionCache,
+    new_version_cache: &VersionCache,
+    relinked_crate_ids: &HashSet<i32>,
+    latest: bool,
+) -> FirstOrLatestVersionDelta {
+    let old_picks: HashMap<i32, i32> = old_version_cache
+        .first_or_latest_versions(latest)
+        .into_iter()
+        .collect();
+
+    let mut delta = FirstOrLatestVersionDelta::default();
+    for (crate_id, version_id) in new_version_cache.first_or_latest_versions(latest) {
+        let relinked = relinked_crate_ids.contains(&crate_id);
+        match old_picks.get(&crate_id) {
+            Some(old_version_id) if *old_version_id == version_id && !relinked => continue,
+            Some(_) => delta.removed_crate_ids.push(crate_id),
+            None if relinked => delta.removed_crate_ids.push(crate_id),
+            None => {}
+        }
+        delta.added.push((crate_id, version_id));
+    }
+
+    delta
+}
@@ -1,3 +1,15 @@
+pub mod bulk_export;
+pub mod checkpoint;
+pub mod config;
 pub mod constants;
+pub mod cypher_files;
+pub mod dump;
+pub mod incremental;
 pub mod models;
+pub mod pipeline;
+pub mod sink;
+pub mod source;
+pub mod stats;
+pub mod unresolved;
 pub mod utils;
+pub mod warmup;
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use chrono::NaiveDateTime;
+
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct CargoUserDBResponse {
     pub id: i32,
@@ -8,11 +10,25 @@ pub struct CargoUserDBResponse {
     pub preferred_name: Option<String>,
 }
 
-#[derive(Debug, sqlx::FromRow)]
+// GitHub team, eg. login "github:rust-lang:libs"
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CargoTeamDBResponse {
+    pub id: i32,
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct CargoCrateDBResponse {
     pub id: i32,
     pub name: String,
-    // TODO: Add description, repository, documentation and homepage (make sure to update sql and redis commands)
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+    // All-time downloads, including the ones of versions deleted since
+    pub downloads: i64,
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -21,15 +37,89 @@ pub struct CargoCrateVersionDBResponse {
     pub crate_id: i32,
     pub num: String,
     pub features: sqlx::types::Json<HashMap<String, Vec<String>>>,
-    // TODO: Add description, repository, documentation and homepage (make sure to update sql and redis commands)
     pub published_by: Option<i32>,
+    pub yanked: bool,
+    // Publish date (UTC), missing from dumps that predate the column
+    pub created_at: Option<NaiveDateTime>,
+    // All-time downloads
+    pub downloads: i64,
+    // Denormalized from the crate, so traversals don't have to look up the crate of every version
     pub crate_name: String,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CargoCategoryDBResponse {
+    pub id: i32,
+    // Display name, eg. "Parser implementations"
+    pub name: String,
+    // Unique, eg. "parser-implementations" or "development-tools::testing" for subcategories
+    pub slug: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CargoKeywordDBResponse {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Deserialize)]
+pub struct CargoCrateCategoryDBResponse {
+    pub crate_id: i32,
+    pub category_id: i32,
+}
+
+#[derive(Debug, sqlx::FromRow, serde::Deserialize)]
+pub struct CargoCrateKeywordDBResponse {
+    pub crate_id: i32,
+    pub keyword_id: i32,
+}
+
+// Owner_id is either a user or a team id, depending on owner_kind
+#[derive(Debug, sqlx::FromRow)]
+pub struct CargoCrateOwnerDBResponse {
+    pub crate_id: i32,
+    pub owner_id: i32,
+    pub owner_kind: CargoOwnerKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::Type)]
+#[repr(i32)]
+pub enum CargoOwnerKind {
+    User = 0,
+    Team = 1,
+}
+
+impl std::convert::TryFrom<i32> for CargoOwnerKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CargoOwnerKind::User),
+            1 => Ok(CargoOwnerKind::Team),
+            _ => anyhow::bail!("Unknown owner kind {value}"),
+        }
+    }
+}
+
+// Downloads of a version on a single day (dump) or summed up over the whole table (postgres).
+// crates.io only keeps the last 90 days.
+#[derive(Debug, sqlx::FromRow)]
+pub struct CargoVersionDownloadsDBResponse {
+    pub version_id: i32,
+    pub downloads: i64,
 }
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct CargoDependenciesDBResponse {
     pub from_version_id: i32,
     pub to_crate_id: i32,
+    // Set when the dependency is renamed (`package = "..."`)
+    pub explicit_name: Option<String>,
     pub required_semver: String,
     pub optional: bool,
     pub default_features: bool,
@@ -42,6 +132,8 @@ pub struct CargoDependenciesDBResponse {
 pub struct CargoDependencyRGEdgeBuilder {
     pub from_version_id: i32,
     pub to_version_id: i32,
+    // Name the dependency is known by inside of the depending crate (renamed or crate name)
+    pub name: String,
     pub required_semver: String,
     pub optional: bool,
     pub with_features: Vec<String>,
@@ -49,7 +141,18 @@ pub struct CargoDependencyRGEdgeBuilder {
     pub kind: CargoDependencyKind,
 }
 
-#[derive(Debug, Clone, PartialEq, sqlx::Type, serde::Serialize)]
+// Links a dependency to one of the versions its requirement matches, not only the picked one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CargoCandidateRGEdgeBuilder {
+    pub from_version_id: i32,
+    pub to_version_id: i32,
+    pub name: String,
+    pub required_semver: String,
+    pub target: Option<String>,
+    pub kind: CargoDependencyKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, sqlx::Type, serde::Serialize)]
 #[repr(i32)]
 pub enum CargoDependencyKind {
     Normal = 0,
@@ -57,6 +160,19 @@ pub enum CargoDependencyKind {
     Dev = 2,
 }
 
+impl std::convert::TryFrom<i32> for CargoDependencyKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CargoDependencyKind::Normal),
+            1 => Ok(CargoDependencyKind::Build),
+            2 => Ok(CargoDependencyKind::Dev),
+            _ => anyhow::bail!("Unknown dependency kind {value}"),
+        }
+    }
+}
+
 impl std::fmt::Display for CargoDependencyKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
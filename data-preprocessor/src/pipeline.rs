@@ -0,0 +1,647 @@
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{DEFAULT_MEMORY_BUDGET_MB, REDIS_INSERTION_CHUNK_SIZE},
+    incremental::{
+        crate_metadata_hash, crates_with_removed_versions, diff_candidate_edges,
+        diff_dependency_edges, diff_first_or_latest_versions, downloads_changed, ExistingGraphIds,
+        SourceDelta,
+    },
+    log_debug,
+    models::CargoOwnerKind,
+    sink::GraphSink,
+    source::DataSource,
+    unresolved::UnresolvedDependencyReport,
+    utils::{
+        gen_candidate_redis_graph_link_query, gen_categories_redis_graph_node_query,
+        gen_crate_category_redis_graph_link_query, gen_crate_keyword_redis_graph_link_query,
+        gen_crate_owner_redis_graph_link_query,
+        gen_crate_versions_downloads_redis_graph_update_query,
+        gen_crate_versions_redis_graph_node_query,
+        gen_crate_versions_yanked_redis_graph_update_query,
+        gen_crates_downloads_redis_graph_update_query,
+        gen_crates_metadata_redis_graph_update_query, gen_crates_redis_graph_node_query,
+        gen_dependency_redis_graph_link_query, gen_feature_dependency_redis_graph_link_query,
+        gen_feature_enables_redis_graph_link_query, gen_features_redis_graph_node_query,
+        gen_first_or_latest_version_redis_graph_link_query_from_picks,
+        gen_keywords_redis_graph_node_query, gen_published_by_redis_graph_link_query,
+        gen_remove_candidate_redis_graph_link_query,
+        gen_remove_crate_category_redis_graph_link_query,
+        gen_remove_crate_keyword_redis_graph_link_query,
+        gen_remove_crate_owner_redis_graph_link_query, gen_remove_crate_versions_redis_graph_query,
+        gen_remove_dependency_redis_graph_link_query,
+        gen_remove_feature_dependency_redis_graph_link_query,
+        gen_remove_first_or_latest_version_redis_graph_link_query,
+        gen_remove_nodes_redis_graph_query, gen_teams_redis_graph_node_query,
+        gen_users_redis_graph_node_query, gen_version_redis_graph_link_query, ResolutionStrategy,
+        VersionCache,
+    },
+};
+
+// Rough upper bound of the memory a single row occupies while in flight:
+// the parsed row itself plus its share of the generated cypher query.
+const ESTIMATED_BYTES_PER_ROW: usize = 2 * 1024;
+// One page buffered by the source, one being converted and the queries generated from it.
+const PAGES_IN_FLIGHT: usize = 3;
+const MIN_PAGE_SIZE: usize = 1_000;
+// Rough size of one entry of the lookups kept until the end of an import, hash map overhead included
+const ESTIMATED_BYTES_PER_RECENT_DOWNLOADS: usize = 32;
+// Name and metadata urls, only kept for dumps (see DataSource::crate_versions)
+const ESTIMATED_BYTES_PER_CRATE: usize = 384;
+// Id, crate id and parsed version of a VersionCache entry
+const ESTIMATED_BYTES_PER_CACHED_VERSION: usize = 128;
+// Feature and dependency name plus the versions already linked, bulk export only
+const ESTIMATED_BYTES_PER_ACTIVATION: usize = 96;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IngestionBudget {
+    pub page_size: usize,
+    // Max. number of rows unwound by a single query
+    pub chunk_size: usize,
+    // Shared by the rows in flight and the lookups kept until the end of the import
+    pub memory_bytes: usize,
+}
+impl IngestionBudget {
+    pub fn from_megabytes(megabytes: usize, chunk_size: usize) -> Self {
+        let memory_bytes = megabytes * 1024 * 1024;
+        let page_size = (memory_bytes / (ESTIMATED_BYTES_PER_ROW * PAGES_IN_FLIGHT))
+            .clamp(MIN_PAGE_SIZE.min(chunk_size), chunk_size);
+        IngestionBudget {
+            page_size,
+            chunk_size,
+            memory_bytes,
+        }
+    }
+
+    // Pages shrink as the kept lookups grow, down to MIN_PAGE_SIZE (the budget is exceeded past that).
+    // Depends only on the source, so resumed imports still generate the same queries.
+    pub fn page_size_after(&self, kept: &KeptEntries) -> usize {
+        let left_bytes = self.memory_bytes.saturating_sub(kept.estimated_bytes());
+        (left_bytes / (ESTIMATED_BYTES_PER_ROW * PAGES_IN_FLIGHT))
+            .min(self.page_size)
+            .max(MIN_PAGE_SIZE.min(self.page_size))
+    }
+}
+
+// Number of entries in the lookups kept until the end of an import
+#[derive(Debug, Default)]
+pub struct KeptEntries {
+    pub recent_downloads: usize,
+    pub crates: usize,
+    pub cached_versions: usize,
+    pub dependency_activations: usize,
+}
+impl KeptEntries {
+    pub fn estimated_bytes(&self) -> usize {
+        self.recent_downloads * ESTIMATED_BYTES_PER_RECENT_DOWNLOADS
+            + self.crates * ESTIMATED_BYTES_PER_CRATE
+            + self.cached_versions * ESTIMATED_BYTES_PER_CACHED_VERSION
+            + self.dependency_activations * ESTIMATED_BYTES_PER_ACTIVATION
+    }
+}
+
+impl Default for IngestionBudget {
+    fn default() -> Self {
+        IngestionBudget::from_megabytes(DEFAULT_MEMORY_BUDGET_MB, REDIS_INSERTION_CHUNK_SIZE)
+    }
+}
+
+pub enum ImportMode {
+    Full,
+    Incremental(Box<ExistingGraphIds>),
+}
+
+// Optional parts of the graph, off by default since they multiply its size
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    // CAN_RESOLVE_TO edges from a version to every version its dependencies' requirements match
+    pub candidate_edges: bool,
+    // DEPENDS_ON_MIN edges to the lowest matching version, next to the usual DEPENDS_ON ones
+    pub minimal_versions: bool,
+    // DEPENDS_ON_AS_OF_PUBLISH edges to the highest version published before the depending one
+    pub as_of_publish_date: bool,
+}
+impl ImportOptions {
+    pub fn resolution_strategies(&self) -> Vec<ResolutionStrategy> {
+        let mut strategies = vec![ResolutionStrategy::Maximal];
+        if self.minimal_versions {
+            strategies.push(ResolutionStrategy::Minimal);
+        }
+        if self.as_of_publish_date {
+            strategies.push(ResolutionStrategy::AsOfPublishDate);
+        }
+        strategies
+    }
+}
+
+// Number of rows read from the source (not only the newly written ones)
+#[derive(Debug, Default)]
+pub struct ImportCounts {
+    pub users: usize,
+    pub crates: usize,
+    pub crate_versions: usize,
+    pub dependencies: usize,
+    // Dependencies that couldn't be linked to any crate version
+    pub unresolved_dependencies: UnresolvedDependencyReport,
+    // (crate_id, name) pairs, see VersionCache::crates_without_parseable_versions
+    pub crates_without_parseable_versions: Vec<(i32, String)>,
+}
+
+// Reads the source page by page, converts every page into cypher queries and hands them over to `sink`
+// before the next page is read. Order of the queries matters (nodes have to exist before they are linked)!
+// Preparing and finishing the sink is up to the caller.
+pub async fn run_import(
+    source: &DataSource,
+    mode: &ImportMode,
+    budget: &IngestionBudget,
+    options: &ImportOptions,
+    sink: &mut dyn GraphSink,
+) -> anyhow::Result<ImportCounts> {
+    let mut flush = |queries: Vec<String>| -> anyhow::Result<()> {
+        for query in queries {
+            sink.execute(query)?;
+        }
+        Ok(())
+    };
+    let existing_ids = match mode {
+        ImportMode::Full => None,
+        ImportMode::Incremental(existing_ids) => Some(existing_ids.as_ref()),
+    };
+    let mut counts = ImportCounts::default();
+
+    log_debug!("Importing users...");
+    // Nodes that aren't in the source anymore (deleted from crates.io) are deleted along with their links
+    let mut users_delta = existing_ids.map(|s| SourceDelta::new(&s.users));
+    let mut users = source.users(budget.page_size);
+    while let Some(page) = users.next_page().await? {
+        counts.users += page.len();
+        let new_users = retain_new(page, users_delta.as_mut(), |s| s.id);
+        flush(gen_users_redis_graph_node_query(
+            &new_users,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = users_delta {
+        flush(gen_remove_nodes_redis_graph_query(
+            &delta.removed(),
+            "CargoUser",
+            budget.chunk_size,
+        )?)?;
+    }
+    log_debug!("Done importing {} users.", counts.users);
+
+    log_debug!("Importing teams...");
+    let mut teams_delta = existing_ids.map(|s| SourceDelta::new(&s.teams));
+    let mut teams = source.teams(budget.page_size);
+    while let Some(page) = teams.next_page().await? {
+        let new_teams = retain_new(page, teams_delta.as_mut(), |s| s.id);
+        flush(gen_teams_redis_graph_node_query(
+            &new_teams,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = teams_delta {
+        flush(gen_remove_nodes_redis_graph_query(
+            &delta.removed(),
+            "CargoTeam",
+            budget.chunk_size,
+        )?)?;
+    }
+    log_debug!("Done importing teams.");
+
+    log_debug!("Reading recent downloads...");
+    let recent_downloads = read_recent_downloads(source, budget.page_size).await?;
+    let mut kept = KeptEntries {
+        recent_downloads: recent_downloads.len(),
+        ..Default::default()
+    };
+    log_debug!(
+        "Done reading recent downloads of {} versions.",
+        recent_downloads.len()
+    );
+
+    log_debug!("Importing crates...");
+    let mut crates_by_id = HashMap::new();
+    // (downloads, recent_downloads), recent ones are summed up over the crate's versions below
+    let mut crate_downloads: HashMap<i32, (i64, i64)> = HashMap::new();
+    let mut crates_delta = existing_ids.map(|s| SourceDelta::new(&s.crates));
+    let mut crates = source.crates(budget.page_size_after(&kept));
+    while let Some(page) = crates.next_page().await? {
+        counts.crates += page.len();
+        crate_downloads.extend(page.iter().map(|s| (s.id, (s.downloads, 0))));
+        if source.crate_versions_need_crates() {
+            crates_by_id.extend(page.iter().map(|s| (s.id, s.clone())));
+        }
+        if let Some(existing_ids) = existing_ids {
+            let changed_crates: Vec<_> = page
+                .iter()
+                .filter(|s| {
+                    existing_ids.crate_metadata.get(&s.id).is_some_and(|hash| {
+                        *hash
+                            != crate_metadata_hash([
+                                s.description.as_deref(),
+                                s.repository.as_deref(),
+                                s.documentation.as_deref(),
+                                s.homepage.as_deref(),
+                            ])
+                    })
+                })
+                .cloned()
+                .collect();
+            flush(gen_crates_metadata_redis_graph_update_query(
+                &changed_crates,
+                budget.chunk_size,
+            )?)?;
+        }
+        let new_crates = retain_new(page, crates_delta.as_mut(), |s| s.id);
+        flush(gen_crates_redis_graph_node_query(
+            &new_crates,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = crates_delta {
+        flush(gen_remove_nodes_redis_graph_query(
+            &delta.removed(),
+            "CargoCrate",
+            budget.chunk_size,
+        )?)?;
+    }
+    kept.crates = crates_by_id.len();
+    log_debug!("Done importing {} crates.", counts.crates);
+
+    log_debug!("Importing crate owners...");
+    // Owners get added and removed without a new release, same as categories and keywords below
+    let mut user_owners_delta = existing_ids.map(|s| SourceDelta::new(&s.user_owners));
+    let mut team_owners_delta = existing_ids.map(|s| SourceDelta::new(&s.team_owners));
+    let mut crate_owners = source.crate_owners(budget.page_size_after(&kept));
+    while let Some(page) = crate_owners.next_page().await? {
+        let new_owners: Vec<_> = page
+            .into_iter()
+            .filter(|s| {
+                let delta = match s.owner_kind {
+                    CargoOwnerKind::User => user_owners_delta.as_mut(),
+                    CargoOwnerKind::Team => team_owners_delta.as_mut(),
+                };
+                delta.is_none_or(|delta| delta.check_off((s.owner_id, s.crate_id)))
+            })
+            .collect();
+        flush(gen_crate_owner_redis_graph_link_query(
+            &new_owners,
+            budget.chunk_size,
+        )?)?;
+    }
+    for (delta, owner_kind) in [
+        (user_owners_delta, CargoOwnerKind::User),
+        (team_owners_delta, CargoOwnerKind::Team),
+    ] {
+        if let Some(delta) = delta {
+            flush(gen_remove_crate_owner_redis_graph_link_query(
+                &delta.removed(),
+                owner_kind,
+                budget.chunk_size,
+            )?)?;
+        }
+    }
+    log_debug!("Done importing crate owners.");
+
+    log_debug!("Importing categories and keywords...");
+    let mut categories_delta = existing_ids.map(|s| SourceDelta::new(&s.categories));
+    let mut categories = source.categories(budget.page_size_after(&kept));
+    while let Some(page) = categories.next_page().await? {
+        let new_categories = retain_new(page, categories_delta.as_mut(), |s| s.id);
+        flush(gen_categories_redis_graph_node_query(
+            &new_categories,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = categories_delta {
+        flush(gen_remove_nodes_redis_graph_query(
+            &delta.removed(),
+            "CargoCategory",
+            budget.chunk_size,
+        )?)?;
+    }
+    let mut keywords_delta = existing_ids.map(|s| SourceDelta::new(&s.keywords));
+    let mut keywords = source.keywords(budget.page_size_after(&kept));
+    while let Some(page) = keywords.next_page().await? {
+        let new_keywords = retain_new(page, keywords_delta.as_mut(), |s| s.id);
+        flush(gen_keywords_redis_graph_node_query(
+            &new_keywords,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = keywords_delta {
+        flush(gen_remove_nodes_redis_graph_query(
+            &delta.removed(),
+            "CargoKeyword",
+            budget.chunk_size,
+        )?)?;
+    }
+    // Crates can change their categories and keywords with every release
+    let mut crate_categories_delta = existing_ids.map(|s| SourceDelta::new(&s.crate_categories));
+    let mut crate_categories = source.crate_categories(budget.page_size_after(&kept));
+    while let Some(page) = crate_categories.next_page().await? {
+        let new_crate_categories = retain_new(page, crate_categories_delta.as_mut(), |s| {
+            (s.crate_id, s.category_id)
+        });
+        flush(gen_crate_category_redis_graph_link_query(
+            &new_crate_categories,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = crate_categories_delta {
+        flush(gen_remove_crate_category_redis_graph_link_query(
+            &delta.removed(),
+            budget.chunk_size,
+        )?)?;
+    }
+    let mut crate_keywords_delta = existing_ids.map(|s| SourceDelta::new(&s.crate_keywords));
+    let mut crate_keywords = source.crate_keywords(budget.page_size_after(&kept));
+    while let Some(page) = crate_keywords.next_page().await? {
+        let new_crate_keywords = retain_new(page, crate_keywords_delta.as_mut(), |s| {
+            (s.crate_id, s.keyword_id)
+        });
+        flush(gen_crate_keyword_redis_graph_link_query(
+            &new_crate_keywords,
+            budget.chunk_size,
+        )?)?;
+    }
+    if let Some(delta) = crate_keywords_delta {
+        flush(gen_remove_crate_keyword_redis_graph_link_query(
+            &delta.removed(),
+            budget.chunk_size,
+        )?)?;
+    }
+    log_debug!("Done importing categories and keywords.");
+
+    log_debug!("Importing crate versions...");
+    // Old cache represents the versions already in the graph (only used in incremental mode)
+    let mut old_version_cache = VersionCache::default();
+    let mut new_version_cache = VersionCache::default();
+    let mut crate_versions_delta = existing_ids.map(|s| SourceDelta::new(&s.crate_versions));
+    // Versions already in the graph by crate, compared with ExistingGraphIds::crate_version_counts
+    let mut listed_version_counts: HashMap<i32, usize> = HashMap::new();
+    let mut crate_versions =
+        source.crate_versions(budget.page_size_after(&kept), Arc::new(crates_by_id));
+    while let Some(page) = crate_versions.next_page().await? {
+        counts.crate_versions += page.len();
+        let mut yanked_changes = vec![];
+        let mut version_downloads = vec![];
+        for version in page.iter() {
+            new_version_cache.insert(version);
+            kept.cached_versions += 1;
+            let version_recent_downloads = recent_downloads.get(&version.id).copied().unwrap_or(0);
+            if downloads_changed(
+                existing_ids.map(|s| &s.crate_version_downloads),
+                version.id,
+                (version.downloads, version_recent_downloads),
+            ) {
+                version_downloads.push((version.id, version.downloads, version_recent_downloads));
+            }
+            crate_downloads.entry(version.crate_id).or_default().1 += version_recent_downloads;
+
+            let Some(existing_ids) = existing_ids else {
+                continue;
+            };
+            if existing_ids.crate_versions.contains(&version.id) {
+                let was_yanked = existing_ids.yanked_crate_versions.contains(&version.id);
+                old_version_cache.insert_with_yanked(version, was_yanked);
+                kept.cached_versions += 1;
+                *listed_version_counts.entry(version.crate_id).or_default() += 1;
+                if was_yanked != version.yanked {
+                    yanked_changes.push((version.id, version.yanked));
+                }
+            }
+        }
+        flush(gen_crate_versions_yanked_redis_graph_update_query(
+            &yanked_changes,
+            budget.chunk_size,
+        )?)?;
+
+        let new_crate_versions = retain_new(page, crate_versions_delta.as_mut(), |s| s.id);
+        flush(gen_crate_versions_redis_graph_node_query(
+            &new_crate_versions,
+            budget.chunk_size,
+        )?)?;
+        flush(gen_features_redis_graph_node_query(
+            &new_crate_versions,
+            budget.chunk_size,
+        )?)?;
+        flush(gen_feature_enables_redis_graph_link_query(
+            &new_crate_versions,
+            budget.chunk_size,
+        )?)?;
+        flush(gen_published_by_redis_graph_link_query(
+            &new_crate_versions,
+            budget.chunk_size,
+        )?)?;
+        flush(gen_version_redis_graph_link_query(
+            &new_crate_versions,
+            budget.chunk_size,
+        )?)?;
+        flush(gen_crate_versions_downloads_redis_graph_update_query(
+            &version_downloads,
+            budget.chunk_size,
+        )?)?;
+    }
+    flush(gen_crates_downloads_redis_graph_update_query(
+        &crate_downloads
+            .into_iter()
+            .filter(|(crate_id, downloads)| {
+                downloads_changed(
+                    existing_ids.map(|s| &s.crate_downloads),
+                    *crate_id,
+                    *downloads,
+                )
+            })
+            .map(|(crate_id, (downloads, recent_downloads))| {
+                (crate_id, downloads, recent_downloads)
+            })
+            .sorted()
+            .collect_vec(),
+        budget.chunk_size,
+    )?)?;
+    if let Some(delta) = crate_versions_delta {
+        flush(gen_remove_crate_versions_redis_graph_query(
+            &delta.removed(),
+            budget.chunk_size,
+        )?)?;
+    }
+    // Empty for full imports
+    let relinked_crate_ids = existing_ids
+        .map(|s| crates_with_removed_versions(&s.crate_version_counts, &listed_version_counts))
+        .unwrap_or_default();
+    counts.crates_without_parseable_versions =
+        new_version_cache.crates_without_parseable_versions();
+    log_debug!("Done importing {} crate versions.", counts.crate_versions);
+
+    log_debug!("Linking first and latest versions...");
+    for latest in [false, true] {
+        if existing_ids.is_some() {
+            let delta = diff_first_or_latest_versions(
+                &old_version_cache,
+                &new_version_cache,
+                &relinked_crate_ids,
+                latest,
+            );
+            flush(gen_remove_first_or_latest_version_redis_graph_link_query(
+                &delta.removed_crate_ids,
+                latest,
+                budget.chunk_size,
+            )?)?;
+            flush(
+                gen_first_or_latest_version_redis_graph_link_query_from_picks(
+                    &delta.added,
+                    latest,
+                    budget.chunk_size,
+                )?,
+            )?;
+        } else {
+            flush(
+                gen_first_or_latest_version_redis_graph_link_query_from_picks(
+                    &new_version_cache.first_or_latest_versions(latest),
+                    latest,
+                    budget.chunk_size,
+                )?,
+            )?;
+        }
+    }
+    log_debug!("Done linking first and latest versions.");
+
+    log_debug!("Resolving and importing dependencies...");
+    let mut dependencies = source.raw_dependencies(budget.page_size_after(&kept));
+    while let Some(page) = dependencies.next_page().await? {
+        counts.dependencies += page.len();
+        for strategy in options.resolution_strategies() {
+            // Whether a dependency resolves doesn't depend on the strategy, so it's reported only once
+            let mut scratch_report = UnresolvedDependencyReport::default();
+            let report = match strategy {
+                ResolutionStrategy::Maximal => &mut counts.unresolved_dependencies,
+                _ => &mut scratch_report,
+            };
+            // Features enable the dependency that actually gets used, the maximal one
+            let link_features = strategy == ResolutionStrategy::Maximal;
+
+            if let Some(existing_ids) = existing_ids {
+                let delta = diff_dependency_edges(
+                    &old_version_cache,
+                    &new_version_cache,
+                    &existing_ids.crate_versions,
+                    &relinked_crate_ids,
+                    &page,
+                    strategy,
+                    report,
+                );
+                flush(gen_remove_dependency_redis_graph_link_query(
+                    &delta.removed,
+                    strategy,
+                    budget.chunk_size,
+                )?)?;
+                if link_features {
+                    flush(gen_remove_feature_dependency_redis_graph_link_query(
+                        &delta.removed,
+                        budget.chunk_size,
+                    )?)?;
+                }
+                flush(gen_dependency_redis_graph_link_query(
+                    &delta.added,
+                    strategy,
+                    budget.chunk_size,
+                )?)?;
+                if link_features {
+                    flush(gen_feature_dependency_redis_graph_link_query(
+                        &delta.added,
+                        budget.chunk_size,
+                    )?)?;
+                }
+            } else {
+                let dependency_edges: Vec<_> = page
+                    .iter()
+                    .filter_map(|dep| report.record(new_version_cache.resolve_with(dep, strategy)))
+                    .collect();
+                flush(gen_dependency_redis_graph_link_query(
+                    &dependency_edges,
+                    strategy,
+                    budget.chunk_size,
+                )?)?;
+                if link_features {
+                    flush(gen_feature_dependency_redis_graph_link_query(
+                        &dependency_edges,
+                        budget.chunk_size,
+                    )?)?;
+                }
+            }
+        }
+
+        if !options.candidate_edges {
+            continue;
+        }
+        if let Some(existing_ids) = existing_ids {
+            let candidate_delta = diff_candidate_edges(
+                &old_version_cache,
+                &new_version_cache,
+                &existing_ids.crate_versions,
+                &page,
+            );
+            flush(gen_remove_candidate_redis_graph_link_query(
+                &candidate_delta.removed,
+                budget.chunk_size,
+            )?)?;
+            flush(gen_candidate_redis_graph_link_query(
+                &candidate_delta.added,
+                budget.chunk_size,
+            )?)?;
+        } else {
+            let candidate_edges: Vec<_> = page
+                .iter()
+                .flat_map(|dep| new_version_cache.candidates(dep))
+                .collect();
+            flush(gen_candidate_redis_graph_link_query(
+                &candidate_edges,
+                budget.chunk_size,
+            )?)?;
+        }
+    }
+    log_debug!(
+        "Done importing {} dependencies ({} unresolved).",
+        counts.dependencies,
+        counts.unresolved_dependencies.unresolved.len()
+    );
+
+    Ok(counts)
+}
+
+// Downloads per version id over the days the source keeps (last 90 days on crates.io).
+// Read before crate versions, so the crate sums can be counted up while versions are streamed.
+pub async fn read_recent_downloads(
+    source: &DataSource,
+    page_size: usize,
+) -> anyhow::Result<HashMap<i32, i64>> {
+    let mut recent_downloads: HashMap<i32, i64> = HashMap::new();
+    let mut version_downloads = source.version_downloads(page_size);
+    while let Some(page) = version_downloads.next_page().await? {
+        for row in page {
+            *recent_downloads.entry(row.version_id).or_default() += row.downloads;
+        }
+    }
+    Ok(recent_downloads)
+}
+
+// Everything is new without a delta (full import)
+fn retain_new<T, K: Copy + Eq + Hash + Ord>(
+    page: Vec<T>,
+    delta: Option<&mut SourceDelta<K>>,
+    key: fn(&T) -> K,
+) -> Vec<T> {
+    match delta {
+        Some(delta) => page
+            .into_iter()
+            .filter(|s| delta.check_off(key(s)))
+            .collect(),
+        None => page,
+    }
+}
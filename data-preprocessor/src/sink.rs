@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use redis::Connection;
+use redis_graph::GraphCommands;
+
+use crate::{
+    checkpoint::ImportCheckpoint,
+    cypher_files::{CypherFileWriter, CypherManifest},
+    log_debug,
+};
+
+// Indexes every full import starts with
+const INDEXES: &[&str] = &[
+    "CREATE INDEX FOR (c:CargoCrate) ON (c.id)",
+    "CREATE INDEX FOR (c:CargoCrate) ON (c.name)",
+    "CREATE INDEX FOR (cv:CargoCrateVersion) ON (cv.id)",
+    "CREATE INDEX FOR (cu:CargoUser) ON (cu.id)",
+    "CREATE INDEX FOR (ct:CargoTeam) ON (ct.id)",
+    "CREATE INDEX FOR (cat:CargoCategory) ON (cat.id)",
+    "CREATE INDEX FOR (cat:CargoCategory) ON (cat.slug)",
+    "CREATE INDEX FOR (ck:CargoKeyword) ON (ck.id)",
+    "CREATE INDEX FOR (f:CargoFeature) ON (f.version_id)",
+    "CREATE INDEX FOR ()-[d:DEPENDS_ON]-() ON (d.kind)",
+    "CREATE INDEX FOR ()-[d:DEPENDS_ON_MIN]-() ON (d.kind)",
+    "CREATE INDEX FOR ()-[d:DEPENDS_ON_AS_OF_PUBLISH]-() ON (d.kind)",
+];
+
+// Destination of the generated queries, in the order they have to be executed in
+pub trait GraphSink {
+    // Called before a full import, leaves an empty graph (with indexes) behind
+    fn prepare_full_import(&mut self) -> anyhow::Result<()>;
+    fn execute(&mut self, query: String) -> anyhow::Result<()>;
+    // Called once every query was handed over
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+// Collects the queries, mostly useful for tests
+impl GraphSink for Vec<String> {
+    fn prepare_full_import(&mut self) -> anyhow::Result<()> {
+        self.clear();
+        Ok(())
+    }
+
+    fn execute(&mut self, query: String) -> anyhow::Result<()> {
+        self.push(query);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+// FalkorDB is the maintained fork of RedisGraph and kept its commands (GRAPH.QUERY, GRAPH.DELETE, ...),
+// both are talked to through the same redis protocol connection, only their url differs.
+pub struct GraphDatabaseSink {
+    redis_conn: Connection,
+    graph_name: String,
+    checkpoint: Option<ImportCheckpoint>,
+}
+impl GraphDatabaseSink {
+    pub fn new(redis_conn: Connection, graph_name: &str) -> Self {
+        GraphDatabaseSink {
+            redis_conn,
+            graph_name: graph_name.to_owned(),
+            checkpoint: None,
+        }
+    }
+
+    // Queries already executed according to the checkpoint are skipped, the rest is recorded in it
+    pub fn with_checkpoint(mut self, checkpoint: ImportCheckpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    pub fn connection(&mut self) -> &mut Connection {
+        &mut self.redis_conn
+    }
+}
+impl GraphSink for GraphDatabaseSink {
+    fn prepare_full_import(&mut self) -> anyhow::Result<()> {
+        log_debug!("Reseting redis to clean state...");
+        // Remove everything
+        let _ = self.redis_conn.graph_delete(&self.graph_name);
+        redis::cmd("flushdb").execute(&mut self.redis_conn);
+
+        // Ensure graph is created and ready
+        self.redis_conn
+            .graph_query(&self.graph_name, "create (:Example {name: 'tmp'})")?;
+        self.redis_conn.graph_query(
+            &self.graph_name,
+            "match (e: Example {name: 'tmp'}) delete e",
+        )?;
+
+        for index in INDEXES {
+            self.redis_conn.graph_query(&self.graph_name, *index)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, query: String) -> anyhow::Result<()> {
+        if let Some(checkpoint) = self.checkpoint.as_mut() {
+            return checkpoint.execute(&mut self.redis_conn, &query);
+        }
+        let answ = self.redis_conn.graph_query(&self.graph_name, query)?;
+
+        log_debug!("{:?}", answ);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(checkpoint) = self.checkpoint.take() {
+            checkpoint.finish(&mut self.redis_conn)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes every query into a numbered .cypher file (see cypher_files), nothing is executed
+pub struct CypherFilesSink {
+    writer: Option<CypherFileWriter>,
+    manifest: Option<CypherManifest>,
+}
+impl CypherFilesSink {
+    pub fn create(
+        dir: &Path,
+        full_import: bool,
+        source: String,
+        page_size: usize,
+    ) -> anyhow::Result<Self> {
+        Ok(CypherFilesSink {
+            writer: Some(CypherFileWriter::create(
+                dir,
+                full_import,
+                source,
+                page_size,
+            )?),
+            manifest: None,
+        })
+    }
+
+    // Available once the sink is finished
+    pub fn manifest(&self) -> Option<&CypherManifest> {
+        self.manifest.as_ref()
+    }
+
+    fn writer(&mut self) -> anyhow::Result<&mut CypherFileWriter> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Cypher files were already finished"))
+    }
+}
+impl GraphSink for CypherFilesSink {
+    // Replaying a full import prepares the graph itself
+    fn prepare_full_import(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn execute(&mut self, query: String) -> anyhow::Result<()> {
+        self.writer()?.write(&query)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            self.manifest = Some(writer.finish()?);
+        }
+        Ok(())
+    }
+}
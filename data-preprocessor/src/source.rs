@@ -0,0 +1,294 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use futures::{stream::BoxStream, TryStreamExt};
+use sqlx::{Pool, Postgres};
+use tokio::sync::mpsc;
+
+use crate::{
+    dump::{
+        read_categories_from_dump, read_crate_categories_from_dump, read_crate_keywords_from_dump,
+        read_crate_owners_from_dump, read_crate_versions_from_dump, read_crates_from_dump,
+        read_keywords_from_dump, read_raw_dependencies_from_dump, read_teams_from_dump,
+        read_users_from_dump, read_version_downloads_from_dump,
+    },
+    models::{
+        CargoCategoryDBResponse, CargoCrateCategoryDBResponse, CargoCrateDBResponse,
+        CargoCrateKeywordDBResponse, CargoCrateOwnerDBResponse, CargoCrateVersionDBResponse,
+        CargoDependenciesDBResponse, CargoKeywordDBResponse, CargoTeamDBResponse,
+        CargoUserDBResponse, CargoVersionDownloadsDBResponse,
+    },
+    utils::{
+        stream_categories_from_db, stream_crate_categories_from_db, stream_crate_keywords_from_db,
+        stream_crate_owners_from_db, stream_crate_versions_from_db, stream_crates_from_db,
+        stream_keywords_from_db, stream_raw_dependencies_from_db, stream_teams_from_db,
+        stream_users_from_db, stream_version_downloads_from_db,
+    },
+};
+
+// At most one page is buffered ahead of the page being processed,
+// the producer is paused until the consumer catches up.
+const PAGES_BUFFERED_AHEAD: usize = 1;
+
+pub enum DataSource {
+    Postgres(Pool<Postgres>),
+    DbDump(PathBuf),
+}
+
+// Identifies the source in import checkpoints
+impl std::fmt::Display for DataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSource::Postgres(_) => write!(f, "postgres"),
+            DataSource::DbDump(dump_path) => write!(f, "{}", dump_path.display()),
+        }
+    }
+}
+
+pub struct PageStream<T> {
+    receiver: mpsc::Receiver<anyhow::Result<Vec<T>>>,
+}
+impl<T> PageStream<T> {
+    pub async fn next_page(&mut self) -> anyhow::Result<Option<Vec<T>>> {
+        match self.receiver.recv().await {
+            Some(page) => page.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl DataSource {
+    pub fn users(&self, page_size: usize) -> PageStream<CargoUserDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => {
+                page_db_stream(pool.clone(), page_size, |pool| stream_users_from_db(pool))
+            }
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_users_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn teams(&self, page_size: usize) -> PageStream<CargoTeamDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => {
+                page_db_stream(pool.clone(), page_size, |pool| stream_teams_from_db(pool))
+            }
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_teams_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn crates(&self, page_size: usize) -> PageStream<CargoCrateDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => {
+                page_db_stream(pool.clone(), page_size, |pool| stream_crates_from_db(pool))
+            }
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_crates_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn crate_owners(&self, page_size: usize) -> PageStream<CargoCrateOwnerDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_crate_owners_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_crate_owners_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn categories(&self, page_size: usize) -> PageStream<CargoCategoryDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_categories_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_categories_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn keywords(&self, page_size: usize) -> PageStream<CargoKeywordDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_keywords_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_keywords_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn crate_categories(&self, page_size: usize) -> PageStream<CargoCrateCategoryDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_crate_categories_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_crate_categories_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn crate_keywords(&self, page_size: usize) -> PageStream<CargoCrateKeywordDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_crate_keywords_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_crate_keywords_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    // Postgres joins crates itself, the dump has to be given the already read crates.
+    pub fn crate_versions_need_crates(&self) -> bool {
+        matches!(self, DataSource::DbDump(_))
+    }
+
+    pub fn crate_versions(
+        &self,
+        page_size: usize,
+        crates: Arc<HashMap<i32, CargoCrateDBResponse>>,
+    ) -> PageStream<CargoCrateVersionDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_crate_versions_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_crate_versions_from_dump(&dump_path, &crates, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn version_downloads(
+        &self,
+        page_size: usize,
+    ) -> PageStream<CargoVersionDownloadsDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_version_downloads_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_version_downloads_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+
+    pub fn raw_dependencies(&self, page_size: usize) -> PageStream<CargoDependenciesDBResponse> {
+        match self {
+            DataSource::Postgres(pool) => page_db_stream(pool.clone(), page_size, |pool| {
+                stream_raw_dependencies_from_db(pool)
+            }),
+            DataSource::DbDump(dump_path) => {
+                let dump_path = dump_path.clone();
+                page_dump_table(page_size, move |on_row| {
+                    read_raw_dependencies_from_dump(&dump_path, on_row)
+                })
+            }
+        }
+    }
+}
+
+fn page_db_stream<T: Send + 'static>(
+    pool: Pool<Postgres>,
+    page_size: usize,
+    query: for<'a> fn(&'a Pool<Postgres>) -> BoxStream<'a, Result<T, sqlx::Error>>,
+) -> PageStream<T> {
+    let (sender, receiver) = mpsc::channel(PAGES_BUFFERED_AHEAD);
+    tokio::spawn(async move {
+        let mut rows = query(&pool);
+        let mut page = Vec::with_capacity(page_size);
+        loop {
+            match rows.try_next().await {
+                Ok(Some(row)) => {
+                    page.push(row);
+                    if page.len() < page_size {
+                        continue;
+                    }
+                    let full_page = std::mem::replace(&mut page, Vec::with_capacity(page_size));
+                    if sender.send(Ok(full_page)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {
+                    if !page.is_empty() {
+                        let _ = sender.send(Ok(page)).await;
+                    }
+                    return;
+                }
+                Err(err) => {
+                    let _ = sender.send(Err(err.into())).await;
+                    return;
+                }
+            }
+        }
+    });
+
+    PageStream { receiver }
+}
+
+type OnRow<'a, T> = &'a mut dyn FnMut(T) -> anyhow::Result<()>;
+
+fn page_dump_table<T, F>(page_size: usize, read_table: F) -> PageStream<T>
+where
+    T: Send + 'static,
+    F: FnOnce(OnRow<T>) -> anyhow::Result<()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(PAGES_BUFFERED_AHEAD);
+    tokio::task::spawn_blocking(move || {
+        let mut page = Vec::with_capacity(page_size);
+        let read_result = read_table(&mut |row| {
+            page.push(row);
+            if page.len() >= page_size {
+                let full_page = std::mem::replace(&mut page, Vec::with_capacity(page_size));
+                sender
+                    .blocking_send(Ok(full_page))
+                    .map_err(|_| anyhow::anyhow!("Page receiver was dropped"))?;
+            }
+            Ok(())
+        });
+
+        let _ = match read_result {
+            Ok(()) if page.is_empty() => Ok(()),
+            Ok(()) => sender.blocking_send(Ok(page)),
+            Err(err) => sender.blocking_send(Err(err)),
+        };
+    });
+
+    PageStream { receiver }
+}
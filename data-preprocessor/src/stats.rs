@@ -0,0 +1,187 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use redis::{Commands, Connection};
+use redis_graph::GraphCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::IMPORT_STATS_KEY_SUFFIX,
+    pipeline::{ImportCounts, ImportOptions},
+};
+
+// Summary of the last import, kept in redis under `<graph name>_import_stats` (read by the API)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportStats {
+    // Unix timestamp (seconds) of the end of the import
+    pub built_at: u64,
+    // Dump path or "postgres"
+    pub source: String,
+    pub incremental: bool,
+    // Incremental imports only diff the parts of the graph these enabled, so they have to keep them as they are
+    #[serde(default)]
+    pub options: ImportOptions,
+    pub source_rows: SourceRowCounts,
+    // Counted in the graph, by label / relation type
+    pub nodes: BTreeMap<String, usize>,
+    pub edges: BTreeMap<String, usize>,
+    pub unresolved_dependencies: usize,
+    pub crates_without_parseable_versions: Vec<String>,
+    // Differences between the source and the graph, empty when everything made it in
+    pub discrepancies: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceRowCounts {
+    pub users: usize,
+    pub crates: usize,
+    pub crate_versions: usize,
+    pub dependencies: usize,
+}
+
+fn stats_key(graph_name: &str) -> String {
+    format!("{graph_name}{IMPORT_STATS_KEY_SUFFIX}")
+}
+
+impl ImportStats {
+    pub fn new(
+        source: String,
+        incremental: bool,
+        options: &ImportOptions,
+        counts: &ImportCounts,
+        nodes: BTreeMap<String, usize>,
+        edges: BTreeMap<String, usize>,
+    ) -> Self {
+        let count =
+            |counted: &BTreeMap<String, usize>, name: &str| counted.get(name).copied().unwrap_or(0);
+        // Every user, crate and version becomes a node, every resolved dependency an edge
+        let expected = [
+            (
+                "CargoUser",
+                "users",
+                counts.users,
+                count(&nodes, "CargoUser"),
+            ),
+            (
+                "CargoCrate",
+                "crates",
+                counts.crates,
+                count(&nodes, "CargoCrate"),
+            ),
+            (
+                "CargoCrateVersion",
+                "crate versions",
+                counts.crate_versions,
+                count(&nodes, "CargoCrateVersion"),
+            ),
+            (
+                "DEPENDS_ON",
+                "resolved dependencies",
+                counts.unresolved_dependencies.resolved,
+                count(&edges, "DEPENDS_ON"),
+            ),
+        ];
+        let discrepancies = expected
+            .into_iter()
+            .filter(|(_, _, in_source, in_graph)| in_source != in_graph)
+            .map(|(name, rows, in_source, in_graph)| {
+                format!("{in_graph} {name} in the graph, but {in_source} {rows} in the source")
+            })
+            .collect();
+
+        ImportStats {
+            built_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|s| s.as_secs())
+                .unwrap_or_default(),
+            source,
+            incremental,
+            options: options.clone(),
+            source_rows: SourceRowCounts {
+                users: counts.users,
+                crates: counts.crates,
+                crate_versions: counts.crate_versions,
+                dependencies: counts.dependencies,
+            },
+            nodes,
+            edges,
+            unresolved_dependencies: counts.unresolved_dependencies.unresolved.len(),
+            crates_without_parseable_versions: counts
+                .crates_without_parseable_versions
+                .iter()
+                .map(|(_, name)| name.clone())
+                .collect(),
+            discrepancies,
+        }
+    }
+
+    // Counts what is actually in the graph (scans every node and edge once)
+    pub fn collect(
+        redis_conn: &mut Connection,
+        graph_name: &str,
+        source: String,
+        incremental: bool,
+        options: &ImportOptions,
+        counts: &ImportCounts,
+    ) -> anyhow::Result<Self> {
+        let nodes = count_grouped(
+            redis_conn,
+            graph_name,
+            "match (n) return labels(n)[0] as name, count(n) as total",
+        )?;
+        let edges = count_grouped(
+            redis_conn,
+            graph_name,
+            "match ()-[r]->() return type(r) as name, count(r) as total",
+        )?;
+        Ok(ImportStats::new(
+            source,
+            incremental,
+            options,
+            counts,
+            nodes,
+            edges,
+        ))
+    }
+
+    // Existing edges are diffed against the ones resolved from the previous source,
+    // edges of an option that was just turned on (or off) would neither be created nor removed
+    pub fn ensure_same_options(&self, options: &ImportOptions) -> anyhow::Result<()> {
+        if &self.options != options {
+            anyhow::bail!(
+                "Graph was imported with {:?}, incremental import with {:?} isn't possible, run a full import instead",
+                self.options,
+                options
+            );
+        }
+        Ok(())
+    }
+
+    pub fn store(&self, redis_conn: &mut Connection, graph_name: &str) -> anyhow::Result<()> {
+        redis_conn.set::<_, _, ()>(stats_key(graph_name), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(redis_conn: &mut Connection, graph_name: &str) -> anyhow::Result<Option<Self>> {
+        let raw_stats: Option<String> = redis_conn.get(stats_key(graph_name))?;
+        Ok(match raw_stats {
+            Some(raw_stats) => Some(serde_json::from_str(&raw_stats)?),
+            None => None,
+        })
+    }
+}
+
+fn count_grouped(
+    redis_conn: &mut Connection,
+    graph_name: &str,
+    query: &str,
+) -> anyhow::Result<BTreeMap<String, usize>> {
+    Ok(redis_conn
+        .graph_ro_query(graph_name, query)?
+        .data
+        .iter()
+        .filter_map(|s| Some((s.get_scalar::<String>("name")?, s.get_scalar("total")?)))
+        .collect())
+}
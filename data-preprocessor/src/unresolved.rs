@@ -0,0 +1,97 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::models::{CargoDependenciesDBResponse, CargoDependencyKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnresolvedReason {
+    // `req` isn't a valid semver requirement
+    BadRequirement,
+    // Depended on crate doesn't have a single version in the source
+    UnknownCrate,
+    // None of the crate's version nums are valid semver versions
+    UnparsableVersionNum,
+    NoMatchingVersion,
+}
+
+// A dependency that was dropped instead of being linked as a DEPENDS_ON edge
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnresolvedDependency {
+    pub from_version_id: i32,
+    pub to_crate_id: i32,
+    // Unknown for unknown crates
+    pub to_crate_name: Option<String>,
+    pub required_semver: String,
+    pub kind: CargoDependencyKind,
+    pub reason: UnresolvedReason,
+    // Parser error of a bad requirement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+impl UnresolvedDependency {
+    pub fn new(
+        dep: &CargoDependenciesDBResponse,
+        to_crate_name: Option<String>,
+        reason: UnresolvedReason,
+    ) -> Self {
+        UnresolvedDependency {
+            from_version_id: dep.from_version_id,
+            to_crate_id: dep.to_crate_id,
+            to_crate_name,
+            required_semver: dep.required_semver.clone(),
+            kind: dep.kind.clone(),
+            reason,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct UnresolvedDependencyReport {
+    pub resolved: usize,
+    pub unresolved: Vec<UnresolvedDependency>,
+}
+impl UnresolvedDependencyReport {
+    // Passes resolved edges through, keeps the rest
+    pub fn record<T>(&mut self, resolution: Result<T, UnresolvedDependency>) -> Option<T> {
+        match resolution {
+            Ok(edge) => {
+                self.resolved += 1;
+                Some(edge)
+            }
+            Err(unresolved) => {
+                self.unresolved.push(unresolved);
+                None
+            }
+        }
+    }
+
+    pub fn count_by_reason(&self) -> BTreeMap<UnresolvedReason, usize> {
+        let mut counts = BTreeMap::new();
+        for unresolved in self.unresolved.iter() {
+            *counts.entry(unresolved.reason).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn to_json(&self, source: &str) -> serde_json::Value {
+        json!({
+            "source": source,
+            "resolved": self.resolved,
+            "unresolved": self.unresolved.len(),
+            "unresolved_by_reason": self.count_by_reason(),
+            "dependencies": self.unresolved,
+        })
+    }
+
+    pub fn write_json(&self, path: &Path, source: &str) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&self.to_json(source))?)?;
+        Ok(())
+    }
+}
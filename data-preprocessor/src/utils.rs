@@ -1,17 +1,20 @@
+use chrono::NaiveDateTime;
+use futures::stream::BoxStream;
 use itertools::Itertools;
-use std::{collections::HashMap, future::Future};
+use std::collections::HashMap;
 
 use semver::{Version, VersionReq};
 use serde_json::json;
 use sqlx::{Pool, Postgres};
 
-use crate::{
-    constants::REDIS_INSERTION_CHUNK_SIZE,
-    models::{
-        CargoCrateDBResponse, CargoCrateVersionDBResponse, CargoDependenciesDBResponse,
-        CargoDependencyRGEdgeBuilder, CargoUserDBResponse,
-    },
+use crate::models::{
+    CargoCandidateRGEdgeBuilder, CargoCategoryDBResponse, CargoCrateCategoryDBResponse,
+    CargoCrateDBResponse, CargoCrateKeywordDBResponse, CargoCrateOwnerDBResponse,
+    CargoCrateVersionDBResponse, CargoDependenciesDBResponse, CargoDependencyRGEdgeBuilder,
+    CargoKeywordDBResponse, CargoOwnerKind, CargoTeamDBResponse, CargoUserDBResponse,
+    CargoVersionDownloadsDBResponse,
 };
+use crate::unresolved::{UnresolvedDependency, UnresolvedDependencyReport, UnresolvedReason};
 
 #[macro_export]
 macro_rules! log_debug {
@@ -21,54 +24,133 @@ macro_rules! log_debug {
     };
 }
 
-pub fn get_users_from_db_async(
+pub fn stream_users_from_db(
     pool: &Pool<Postgres>,
-) -> impl Future<Output = Result<Vec<CargoUserDBResponse>, sqlx::Error>> + '_ {
+) -> BoxStream<'_, Result<CargoUserDBResponse, sqlx::Error>> {
     sqlx::query_as::<_, CargoUserDBResponse>(
         r#"
-            select id, gh_login as "gh_username", gh_avatar, name as "preferred_name" from users;
+            select id, gh_login as "gh_username", gh_avatar, name as "preferred_name" from users order by id;
         "#,
     )
-    .fetch_all(pool)
+    .fetch(pool)
 }
 
-pub fn get_crates_from_db_async(
+pub fn stream_teams_from_db(
     pool: &Pool<Postgres>,
-) -> impl Future<Output = Result<Vec<CargoCrateDBResponse>, sqlx::Error>> + '_ {
+) -> BoxStream<'_, Result<CargoTeamDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoTeamDBResponse>(
+        r#"
+            select id, login, name, avatar from teams order by id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_crates_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoCrateDBResponse, sqlx::Error>> {
     sqlx::query_as::<_, CargoCrateDBResponse>(
         r#"
-            select id, name from crates;
+            select id, name, description, repository, documentation, homepage, downloads::bigint as downloads from crates order by id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_crate_owners_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoCrateOwnerDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoCrateOwnerDBResponse>(
+        r#"
+            select crate_id, owner_id, owner_kind from crate_owners where not deleted order by crate_id, owner_kind, owner_id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_categories_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoCategoryDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoCategoryDBResponse>(
+        r#"
+            select id, category as "name", slug, description from categories order by id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_keywords_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoKeywordDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoKeywordDBResponse>(
+        r#"
+            select id, keyword as "name" from keywords order by id;
         "#,
     )
-    .fetch_all(pool)
+    .fetch(pool)
 }
 
-pub fn get_crate_versions_from_db_async(
+pub fn stream_crate_categories_from_db(
     pool: &Pool<Postgres>,
-) -> impl Future<Output = Result<Vec<CargoCrateVersionDBResponse>, sqlx::Error>> + '_ {
+) -> BoxStream<'_, Result<CargoCrateCategoryDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoCrateCategoryDBResponse>(
+        r#"
+            select crate_id, category_id from crates_categories order by crate_id, category_id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_crate_keywords_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoCrateKeywordDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoCrateKeywordDBResponse>(
+        r#"
+            select crate_id, keyword_id from crates_keywords order by crate_id, keyword_id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_crate_versions_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoCrateVersionDBResponse, sqlx::Error>> {
     sqlx::query_as::<_, CargoCrateVersionDBResponse>(
         r#"
-            select v.id, v.crate_id, v.num, v.features, v.published_by, c.name as crate_name from versions v left join crates c on v.crate_id = c.id order by v.id;
+            select v.id, v.crate_id, v.num, v.features, v.published_by, v.yanked, v.created_at, v.downloads::bigint as downloads, c.name as crate_name, c.description, c.repository, c.documentation, c.homepage from versions v left join crates c on v.crate_id = c.id order by v.id;
+        "#,
+    )
+    .fetch(pool)
+}
+
+pub fn stream_version_downloads_from_db(
+    pool: &Pool<Postgres>,
+) -> BoxStream<'_, Result<CargoVersionDownloadsDBResponse, sqlx::Error>> {
+    sqlx::query_as::<_, CargoVersionDownloadsDBResponse>(
+        r#"
+            select version_id, sum(downloads)::bigint as downloads from version_downloads group by version_id order by version_id;
         "#,
     )
-    .fetch_all(pool)
+    .fetch(pool)
 }
 
-pub fn get_raw_dependencies_from_db_async(
+pub fn stream_raw_dependencies_from_db(
     pool: &Pool<Postgres>,
-) -> impl Future<Output = Result<Vec<CargoDependenciesDBResponse>, sqlx::Error>> + '_ {
+) -> BoxStream<'_, Result<CargoDependenciesDBResponse, sqlx::Error>> {
     sqlx::query_as::<_, CargoDependenciesDBResponse>(
         r#"
-            select version_id "from_version_id", crate_id "to_crate_id", req "required_semver", optional, default_features, features, target, kind from dependencies;
+            select version_id "from_version_id", crate_id "to_crate_id", explicit_name, req "required_semver", optional, default_features, features, target, kind from dependencies order by id;
         "#,
     )
-    .fetch_all(pool)
+    .fetch(pool)
 }
 
 pub fn gen_users_redis_graph_node_query(
     users: &[CargoUserDBResponse],
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
     gen_redis_creation_command(
+        chunk_size,
         users
             .iter()
             .map(|s| {
@@ -89,40 +171,443 @@ pub fn gen_users_redis_graph_node_query(
 
 pub fn gen_crates_redis_graph_node_query(
     crates: &[CargoCrateDBResponse],
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
     gen_redis_creation_command(
+        chunk_size,
         crates
             .iter()
-            .map(|s| format!("[{},{}]", s.id, json!(s.name),))
+            .map(|s| {
+                format!(
+                    "[{},{},{},{},{},{}]",
+                    s.id,
+                    json!(s.name),
+                    json!(s.description),
+                    json!(s.repository),
+                    json!(s.documentation),
+                    json!(s.homepage)
+                )
+            })
+            .collect(),
+        Some("create (:CargoCrate {id: map[0], name: map[1], description: map[2], repository: map[3], documentation: map[4], homepage: map[5]})"),
+    )
+}
+
+pub fn gen_categories_redis_graph_node_query(
+    categories: &[CargoCategoryDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        categories
+            .iter()
+            .map(|s| {
+                format!(
+                    "[{}, {}, {}, {}]",
+                    s.id,
+                    json!(s.name),
+                    json!(s.slug),
+                    json!(s.description)
+                )
+            })
+            .collect(),
+        Some(
+            "create (:CargoCategory {id: map[0], name: map[1], slug: map[2], description: map[3]})",
+        ),
+    )
+}
+
+pub fn gen_keywords_redis_graph_node_query(
+    keywords: &[CargoKeywordDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        keywords
+            .iter()
+            .map(|s| format!("[{}, {}]", s.id, json!(s.name)))
             .collect(),
-        Some("create (:CargoCrate {id: map[0], name: map[1]})"),
+        Some("create (:CargoKeyword {id: map[0], name: map[1]})"),
+    )
+}
+
+pub fn gen_teams_redis_graph_node_query(
+    teams: &[CargoTeamDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        teams
+            .iter()
+            .map(|s| {
+                format!(
+                    "[{}, {}, {}, {}]",
+                    s.id,
+                    json!(s.login),
+                    json!(s.name),
+                    json!(s.avatar)
+                )
+            })
+            .collect(),
+        Some("create (:CargoTeam {id: map[0], login: map[1], name: map[2], avatar: map[3]})"),
+    )
+}
+
+// User owners are linked first, then team owners
+pub fn gen_crate_owner_redis_graph_link_query(
+    crate_owners: &[CargoCrateOwnerDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    let (user_owners, team_owners): (Vec<_>, Vec<_>) = crate_owners
+        .iter()
+        .partition(|s| s.owner_kind == CargoOwnerKind::User);
+    let mut queries = gen_redis_creation_command(
+        chunk_size,
+        user_owners
+            .iter()
+            .map(|s| format!("[{}, {}]", s.owner_id, s.crate_id))
+            .collect(),
+        Some("MATCH (cu:CargoUser {id: map[0]}), (cc:CargoCrate {id: map[1]}) CREATE (cu)-[:OWNS]->(cc)"),
+    )?;
+    queries.extend(gen_redis_creation_command(
+        chunk_size,
+        team_owners
+            .iter()
+            .map(|s| format!("[{}, {}]", s.owner_id, s.crate_id))
+            .collect(),
+        Some("MATCH (ct:CargoTeam {id: map[0]}), (cc:CargoCrate {id: map[1]}) CREATE (ct)-[:OWNS]->(cc)"),
+    )?);
+    Ok(queries)
+}
+
+// Takes (owner_id, crate_id) pairs of a single kind of owner
+pub fn gen_remove_crate_owner_redis_graph_link_query(
+    crate_owners: &[(i32, i32)],
+    owner_kind: CargoOwnerKind,
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    let owner_label = match owner_kind {
+        CargoOwnerKind::User => "CargoUser",
+        CargoOwnerKind::Team => "CargoTeam",
+    };
+    gen_redis_creation_command(
+        chunk_size,
+        crate_owners
+            .iter()
+            .map(|(owner_id, crate_id)| format!("[{}, {}]", owner_id, crate_id))
+            .collect(),
+        Some(format!("MATCH (:{owner_label} {{id: map[0]}})-[r:OWNS]->(:CargoCrate {{id: map[1]}}) DELETE r").as_str()),
+    )
+}
+
+pub fn gen_crate_category_redis_graph_link_query(
+    crate_categories: &[CargoCrateCategoryDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_categories
+            .iter()
+            .map(|s| format!("[{}, {}]", s.crate_id, s.category_id))
+            .collect(),
+        Some("MATCH (cc:CargoCrate {id: map[0]}), (cat:CargoCategory {id: map[1]}) CREATE (cc)-[:IN_CATEGORY]->(cat)"),
+    )
+}
+
+pub fn gen_crate_keyword_redis_graph_link_query(
+    crate_keywords: &[CargoCrateKeywordDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_keywords
+            .iter()
+            .map(|s| format!("[{}, {}]", s.crate_id, s.keyword_id))
+            .collect(),
+        Some("MATCH (cc:CargoCrate {id: map[0]}), (ck:CargoKeyword {id: map[1]}) CREATE (cc)-[:HAS_KEYWORD]->(ck)"),
+    )
+}
+
+// Takes (crate_id, category_id) pairs
+pub fn gen_remove_crate_category_redis_graph_link_query(
+    crate_categories: &[(i32, i32)],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_categories
+            .iter()
+            .map(|(crate_id, category_id)| format!("[{}, {}]", crate_id, category_id))
+            .collect(),
+        Some("MATCH (:CargoCrate {id: map[0]})-[r:IN_CATEGORY]->(:CargoCategory {id: map[1]}) DELETE r"),
+    )
+}
+
+// Nodes the source doesn't list anymore, their links are deleted with them
+pub fn gen_remove_nodes_redis_graph_query(
+    ids: &[i32],
+    label: &str,
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        ids.iter().map(|s| s.to_string()).collect(),
+        Some(&format!("MATCH (n:{label} {{id: map}}) DETACH DELETE n")),
+    )
+}
+
+// Same as gen_remove_nodes_redis_graph_query, features of the versions go with them
+pub fn gen_remove_crate_versions_redis_graph_query(
+    version_ids: &[i32],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        version_ids.iter().map(|s| s.to_string()).collect(),
+        Some("MATCH (cv:CargoCrateVersion {id: map}) OPTIONAL MATCH (cv)-[:HAS_FEATURE]->(f:CargoFeature) DETACH DELETE f, cv"),
+    )
+}
+
+// Takes (crate_id, keyword_id) pairs
+pub fn gen_remove_crate_keyword_redis_graph_link_query(
+    crate_keywords: &[(i32, i32)],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_keywords
+            .iter()
+            .map(|(crate_id, keyword_id)| format!("[{}, {}]", crate_id, keyword_id))
+            .collect(),
+        Some("MATCH (:CargoCrate {id: map[0]})-[r:HAS_KEYWORD]->(:CargoKeyword {id: map[1]}) DELETE r"),
+    )
+}
+
+// Crate metadata can be edited at any time, updates the crate and the copy on each of its versions
+pub fn gen_crates_metadata_redis_graph_update_query(
+    crates: &[CargoCrateDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crates
+            .iter()
+            .map(|s| {
+                format!(
+                    "[{},{},{},{},{}]",
+                    s.id,
+                    json!(s.description),
+                    json!(s.repository),
+                    json!(s.documentation),
+                    json!(s.homepage)
+                )
+            })
+            .collect(),
+        Some("MATCH (cc:CargoCrate {id: map[0]}) SET cc.description = map[1], cc.repository = map[2], cc.documentation = map[3], cc.homepage = map[4] WITH cc, map MATCH (cc)-[:VERSION]->(cv:CargoCrateVersion) SET cv.description = map[1], cv.repository = map[2], cv.documentation = map[3], cv.homepage = map[4]"),
+    )
+}
+
+// Dates are stored as unix timestamps (seconds), redisgraph doesn't have a date type
+pub fn unix_timestamp(date: &Option<NaiveDateTime>) -> Option<i64> {
+    date.map(|s| s.and_utc().timestamp())
+}
+
+// Download counts change every day, so they are (re)set on every import. Takes (id, downloads, recent_downloads) triples.
+pub fn gen_crates_downloads_redis_graph_update_query(
+    crate_downloads: &[(i32, i64, i64)],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_downloads
+            .iter()
+            .map(|(crate_id, downloads, recent_downloads)| {
+                format!("[{}, {}, {}]", crate_id, downloads, recent_downloads)
+            })
+            .collect(),
+        Some("MATCH (cc:CargoCrate {id: map[0]}) SET cc.downloads = map[1], cc.recent_downloads = map[2]"),
+    )
+}
+
+pub fn gen_crate_versions_downloads_redis_graph_update_query(
+    version_downloads: &[(i32, i64, i64)],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        version_downloads
+            .iter()
+            .map(|(version_id, downloads, recent_downloads)| {
+                format!("[{}, {}, {}]", version_id, downloads, recent_downloads)
+            })
+            .collect(),
+        Some("MATCH (cv:CargoCrateVersion {id: map[0]}) SET cv.downloads = map[1], cv.recent_downloads = map[2]"),
     )
 }
 
 pub fn gen_crate_versions_redis_graph_node_query(
     crate_versions: &[CargoCrateVersionDBResponse],
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
     gen_redis_creation_command(
+        chunk_size,
         crate_versions
             .iter()
             .map(|s| {
                 format!(
-                    "[{},{},{}, {}]",
+                    "[{},{}, {}, {}, {}, {}, {}, {}, {}]",
                     s.id,
                     json!(s.num),
-                    json!(json!(s.features).to_string()), // TODO: Dump hack, fix
-                    json!(s.crate_name)
+                    json!(s.crate_name),
+                    s.yanked,
+                    json!(s.description),
+                    json!(s.repository),
+                    json!(s.documentation),
+                    json!(s.homepage),
+                    json!(unix_timestamp(&s.created_at))
                 )
             })
             .collect(),
-        Some("create (:CargoCrateVersion {id: map[0], num: map[1], features: map[2], crate_name: map[3]})"),
+        Some("create (:CargoCrateVersion {id: map[0], num: map[1], crate_name: map[2], yanked: map[3], description: map[4], repository: map[5], documentation: map[6], homepage: map[7], created_at: map[8]})"),
+    )
+}
+
+// Every feature of a version becomes a node, `enables` keeps the raw entries
+// (`other_feature`, `dep:name`, `name/feature`, `name?/feature`) exactly like Cargo.toml has them,
+// `activates` the dependencies they activate (see activated_dependency_names).
+pub fn gen_features_redis_graph_node_query(
+    crate_versions: &[CargoCrateVersionDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_versions
+            .iter()
+            .flat_map(|s| {
+                s.features
+                    .iter()
+                    .sorted_by_key(|(name, _)| *name)
+                    .map(|(name, enables)| {
+                        format!(
+                            "[{}, {}, {}, {}]",
+                            s.id,
+                            json!(name),
+                            json!(enables),
+                            json!(activated_dependency_names(&s.features, enables))
+                        )
+                    })
+            })
+            .collect(),
+        Some("MATCH (cv:CargoCrateVersion {id: map[0]}) CREATE (cv)-[:HAS_FEATURE]->(:CargoFeature {version_id: map[0], name: map[1], enables: map[2], activates: map[3]})"),
+    )
+}
+
+// Links features to the features (of the same version) they enable.
+// Entries that aren't declared features refer to (optional) dependencies, see gen_feature_dependency_redis_graph_link_query.
+pub fn gen_feature_enables_redis_graph_link_query(
+    crate_versions: &[CargoCrateVersionDBResponse],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_versions
+            .iter()
+            .flat_map(|s| {
+                s.features
+                    .iter()
+                    .sorted_by_key(|(name, _)| *name)
+                    .flat_map(move |(name, enables)| {
+                        enables
+                            .iter()
+                            .filter(|enabled| s.features.contains_key(*enabled))
+                            .map(move |enabled| format!("[{}, {}, {}]", s.id, json!(name), json!(enabled)))
+                    })
+            })
+            .collect(),
+        Some("MATCH (f_from:CargoFeature {version_id: map[0], name: map[1]}), (f_to:CargoFeature {version_id: map[0], name: map[2]}) CREATE (f_from)-[:ENABLES]->(f_to)"),
+    )
+}
+
+// Dependency (by its local name) a feature entry activates, plain `name` only refers to a dependency when it isn't a declared feature
+pub fn activated_dependency_name(enabled: &str) -> Option<&str> {
+    if let Some(dependency_name) = enabled.strip_prefix("dep:") {
+        return Some(dependency_name);
+    }
+    match enabled.split_once('/') {
+        Some((dependency_name, _)) if dependency_name.ends_with('?') => None,
+        Some((dependency_name, _)) => Some(dependency_name),
+        None => Some(enabled),
+    }
+}
+
+// Dependencies a feature (with the given entries) of a version activates, without duplicates.
+// Shared by the queries and the bulk export, so both link the same dependencies.
+pub fn activated_dependency_names<'a>(
+    features: &HashMap<String, Vec<String>>,
+    enables: &'a [String],
+) -> Vec<&'a str> {
+    enables
+        .iter()
+        .filter(|enabled| !features.contains_key(*enabled))
+        .filter_map(|enabled| activated_dependency_name(enabled))
+        .unique()
+        .collect()
+}
+
+// Links features to the dependencies they activate (see activated_dependency_names).
+// Has to run after the DEPENDS_ON edges exist, since features refer to dependencies by their local name.
+pub fn gen_feature_dependency_redis_graph_link_query(
+    dependencies: &[CargoDependencyRGEdgeBuilder],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        dependencies
+            .iter()
+            .map(|s| format!("[{}, {}, {}]", s.from_version_id, s.to_version_id, json!(s.name)))
+            .unique()
+            .collect(),
+        Some("MATCH (:CargoCrateVersion {id: map[0]})-[:HAS_FEATURE]->(f:CargoFeature), (cv_to:CargoCrateVersion {id: map[1]}) WHERE map[2] IN f.activates MERGE (f)-[:ENABLES]->(cv_to)"),
+    )
+}
+
+// Has to run after the DEPENDS_ON edges were removed, keeps links to versions that are still depended on.
+pub fn gen_remove_feature_dependency_redis_graph_link_query(
+    dependencies: &[CargoDependencyRGEdgeBuilder],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        dependencies
+            .iter()
+            .map(|s| format!("[{}, {}]", s.from_version_id, s.to_version_id))
+            .unique()
+            .collect(),
+        Some("MATCH (cv_from:CargoCrateVersion {id: map[0]})-[:HAS_FEATURE]->(:CargoFeature)-[e:ENABLES]->(cv_to:CargoCrateVersion {id: map[1]}) WHERE NOT (cv_from)-[:DEPENDS_ON]->(cv_to) DELETE e"),
+    )
+}
+
+// Versions can be yanked / unyanked after they have been published, takes (version_id, yanked) pairs
+pub fn gen_crate_versions_yanked_redis_graph_update_query(
+    yanked_changes: &[(i32, bool)],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        yanked_changes
+            .iter()
+            .map(|(version_id, yanked)| format!("[{}, {}]", version_id, yanked))
+            .collect(),
+        Some("MATCH (cv:CargoCrateVersion {id: map[0]}) SET cv.yanked = map[1]"),
     )
 }
 
 pub fn gen_published_by_redis_graph_link_query(
     crate_versions: &[CargoCrateVersionDBResponse],
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
     gen_redis_creation_command(
+        chunk_size,
         crate_versions.iter().filter(|s| s.published_by.is_some()).map(|s| {
             format!(
                 "[{}, {}]",
@@ -136,8 +621,10 @@ pub fn gen_published_by_redis_graph_link_query(
 
 pub fn gen_version_redis_graph_link_query(
     crate_versions: &[CargoCrateVersionDBResponse],
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
     gen_redis_creation_command(
+        chunk_size,
         crate_versions.iter().map(|s| {
             format!(
                 "[{}, {}]",
@@ -151,50 +638,78 @@ pub fn gen_version_redis_graph_link_query(
 
 pub fn gen_dependency_redis_graph_link_query(
     dependencies: &[CargoDependencyRGEdgeBuilder],
+    strategy: ResolutionStrategy,
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
-    // TODO: Add required_semver to label as property for analysis purposes, however it's not needed for core functionality,
     gen_redis_creation_command(
+        chunk_size,
         dependencies.iter().map(|s| {
             format!(
-                "[{}, {}, {}, {}, {}, {}]",
+                "[{}, {}, {}, {}, {}, {}, {}, {}]",
                 s.from_version_id,
                 s.to_version_id,
                 s.optional,
                 json!(s.with_features),
                 json!(s.target),
                 s.kind,
+                json!(s.name),
+                json!(s.required_semver),
             )
         }).collect(),
-        Some("MATCH (cv_from:CargoCrateVersion {id: map[0]}), (cv_to:CargoCrateVersion {id: map[1]}) CREATE (cv_from)-[:DEPENDS_ON {optional: map[2], with_features: map[3], target: map[4], kind: map[5]}]->(cv_to)")
+        Some(format!("MATCH (cv_from:CargoCrateVersion {{id: map[0]}}), (cv_to:CargoCrateVersion {{id: map[1]}}) CREATE (cv_from)-[:{} {{optional: map[2], with_features: map[3], target: map[4], kind: map[5], name: map[6], required_semver: map[7]}}]->(cv_to)", strategy.edge_type()).as_str())
     )
 }
 
-// TODO: Same problems as with connect_db_dependencies apply here
+pub fn gen_remove_dependency_redis_graph_link_query(
+    dependencies: &[CargoDependencyRGEdgeBuilder],
+    strategy: ResolutionStrategy,
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        dependencies.iter().map(|s| {
+            format!(
+                "[{}, {}, {}, {}, {}, {}, {}]",
+                s.from_version_id,
+                s.to_version_id,
+                s.kind,
+                json!(s.target.clone().unwrap_or_default()),
+                json!(s.required_semver),
+                json!(s.name),
+                s.optional,
+            )
+        }).collect(),
+        // A version can depend on the same crate several times (renamed, per target, optional next to required, ...),
+        // so every distinguishing property is matched.
+        // Edges created before required_semver / name were stored don't have them, those are matched regardless.
+        Some(format!("MATCH (cv_from:CargoCrateVersion {{id: map[0]}})-[d:{}]->(cv_to:CargoCrateVersion {{id: map[1]}}) WHERE d.kind = map[2] AND coalesce(d.target, '') = map[3] AND coalesce(d.required_semver, map[4]) = map[4] AND coalesce(d.name, map[5]) = map[5] AND d.optional = map[6] DELETE d", strategy.edge_type()).as_str())
+    )
+}
+
+// See VersionCache::first_or_latest_versions
 pub fn gen_first_or_latest_version_redis_graph_link_query(
     crate_versions: &[CargoCrateVersionDBResponse],
     latest: bool,
+    chunk_size: usize,
 ) -> anyhow::Result<Vec<String>> {
-    let mut versions = Vec::with_capacity(crate_versions.len());
-    versions.extend_from_slice(crate_versions);
-    versions.sort_by_key(|s| s.id);
-
-    let mapped_data: Vec<String> = if latest {
-        versions
-            .iter()
-            .rev()
-            .unique_by(|version| version.crate_id)
-            .map(|s| format!("[{}, {}]", json!(s.crate_id), json!(s.id)))
-            .collect()
-    } else {
-        versions
-            .iter()
-            .unique_by(|version| version.crate_id)
-            .map(|s| format!("[{}, {}]", json!(s.crate_id), json!(s.id)))
-            .collect()
-    };
+    gen_first_or_latest_version_redis_graph_link_query_from_picks(
+        &VersionCache::new(crate_versions).first_or_latest_versions(latest),
+        latest,
+        chunk_size,
+    )
+}
 
+pub fn gen_first_or_latest_version_redis_graph_link_query_from_picks(
+    picks: &[(i32, i32)],
+    latest: bool,
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
     gen_redis_creation_command(
-        mapped_data,
+        chunk_size,
+        picks
+            .iter()
+            .map(|(crate_id, version_id)| format!("[{}, {}]", crate_id, version_id))
+            .collect(),
         Some(
             format!("MATCH (cc:CargoCrate {{id: map[0]}}), (cv:CargoCrateVersion {{id: map[1]}}) CREATE (cc)-[:{}]->(cv)",
             if latest { "LATEST_VERSION" } else { "FIRST_VERSION" }
@@ -202,13 +717,36 @@ pub fn gen_first_or_latest_version_redis_graph_link_query(
     )
 }
 
+pub fn gen_remove_first_or_latest_version_redis_graph_link_query(
+    crate_ids: &[i32],
+    latest: bool,
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        crate_ids.iter().map(|s| s.to_string()).collect(),
+        Some(
+            format!(
+                "MATCH (cc:CargoCrate {{id: map}})-[l:{}]->(:CargoCrateVersion) DELETE l",
+                if latest {
+                    "LATEST_VERSION"
+                } else {
+                    "FIRST_VERSION"
+                }
+            )
+            .as_str(),
+        ),
+    )
+}
+
 fn gen_redis_creation_command(
+    chunk_size: usize,
     mapped_data: Vec<String>,
     query_to_append_to_end_of_each_chunk: Option<&str>,
 ) -> anyhow::Result<Vec<String>> {
     let mut commands: Vec<String> = vec![];
 
-    for data_row in mapped_data.chunks(REDIS_INSERTION_CHUNK_SIZE) {
+    for data_row in mapped_data.chunks(chunk_size.max(1)) {
         let mut query_builder = string_builder::Builder::default();
         query_builder.append("unwind [");
         query_builder.append(data_row.join(",").trim_end_matches(','));
@@ -220,71 +758,332 @@ fn gen_redis_creation_command(
     Ok(commands)
 }
 
-// Assumes db_create_versions are ordered by id and smaller id == smaller version
-// (which should be true in theory, because it makes sense, but I haven't checked for it in the dataset)
-// TODO: Above assumptions could be flawed, try ordering by semver in the version caching phase and see how much of a performance hit it will be
-// TODO: Does not automatically "upgrade" to latest version, causing some packages not matching. fix it. https://doc.rust-lang.org/cargo/reference/resolver.html#pre-releases
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResolutionStrategy {
+    // Highest matching version, what `cargo update` picks
+    Maximal,
+    // Lowest matching version, what `cargo update -Z minimal-versions` picks
+    Minimal,
+    // Highest matching version published before the depending version,
+    // what a fresh build would have picked on the day it was released
+    AsOfPublishDate,
+}
+impl ResolutionStrategy {
+    // Every strategy gets an edge type of its own, so the resolutions can live next to each other
+    pub fn edge_type(&self) -> &'static str {
+        match self {
+            ResolutionStrategy::Maximal => "DEPENDS_ON",
+            ResolutionStrategy::Minimal => "DEPENDS_ON_MIN",
+            ResolutionStrategy::AsOfPublishDate => "DEPENDS_ON_AS_OF_PUBLISH",
+        }
+    }
+}
+
 #[derive(Debug)]
-struct VersionCacher<'a> {
+struct VersionCacher {
+    id: i32,
     parsed_version: Version,
-    original_crate_version: &'a CargoCrateVersionDBResponse,
+    yanked: bool,
+    created_at: Option<NaiveDateTime>,
 }
-pub fn connect_db_dependencies(
-    db_crate_versions: &Vec<CargoCrateVersionDBResponse>,
-    db_dependencies: &Vec<CargoDependenciesDBResponse>,
-) -> Vec<CargoDependencyRGEdgeBuilder> {
-    // Cache crate versions
-    let mut version_hashmap: HashMap<i32, Vec<VersionCacher>> = HashMap::new();
-    for version in db_crate_versions {
-        version_hashmap
-            .entry(version.crate_id)
-            .or_insert_with(Vec::new);
+
+// Keeps only what is needed to resolve dependencies and pick first / latest versions,
+// so crate versions can be streamed in pages and dropped once they are written to the graph.
+#[derive(Debug, Default)]
+pub struct VersionCache {
+    crate_names: HashMap<i32, String>,
+    // Ordered by semver (ties, e.g. differing only in build metadata, by id).
+    // Ids can't be used, patch releases are often backported after a new major / minor has been published.
+    versions_by_crate: HashMap<i32, Vec<VersionCacher>>,
+    // ((publish date, id) of the first published version, highest version id), including unparsable version nums.
+    // Versions without a publish date (never the case on crates.io) count as published first.
+    first_and_latest_by_crate: HashMap<i32, ((Option<NaiveDateTime>, i32), i32)>,
+    // Publish dates of depending versions (again including unparsable nums)
+    created_at_by_version: HashMap<i32, NaiveDateTime>,
+}
+impl VersionCache {
+    pub fn new<'a>(
+        db_crate_versions: impl IntoIterator<Item = &'a CargoCrateVersionDBResponse>,
+    ) -> Self {
+        let mut version_cache = VersionCache::default();
+        for version in db_crate_versions {
+            version_cache.insert(version);
+        }
+        version_cache
+    }
+
+    pub fn insert(&mut self, version: &CargoCrateVersionDBResponse) {
+        self.insert_with_yanked(version, version.yanked);
     }
-    for version in db_crate_versions {
+
+    // Allows caching a version with the yanked state it had at some other point in time (e.g. in the existing graph)
+    pub fn insert_with_yanked(&mut self, version: &CargoCrateVersionDBResponse, yanked: bool) {
+        let published = (version.created_at, version.id);
+        self.first_and_latest_by_crate
+            .entry(version.crate_id)
+            .and_modify(|(first, latest)| {
+                *first = (*first).min(published);
+                *latest = (*latest).max(version.id);
+            })
+            .or_insert((published, version.id));
+
+        self.crate_names
+            .entry(version.crate_id)
+            .or_insert_with(|| version.crate_name.clone());
+        if let Some(created_at) = version.created_at {
+            self.created_at_by_version.insert(version.id, created_at);
+        }
+
+        let current_versions = self.versions_by_crate.entry(version.crate_id).or_default();
         if let Ok(parsed_version) = Version::parse(&version.num) {
-            let current_versions = version_hashmap.get_mut(&version.crate_id).unwrap();
-            current_versions.push(VersionCacher {
-                parsed_version,
-                original_crate_version: version,
-            });
+            let position = current_versions
+                .partition_point(|s| (&s.parsed_version, s.id) < (&parsed_version, version.id));
+            current_versions.insert(
+                position,
+                VersionCacher {
+                    id: version.id,
+                    parsed_version,
+                    yanked,
+                    created_at: version.created_at,
+                },
+            );
         }
     }
 
-    // Connect best possible matches
-    // Going from top to bottom (since most packages depend on latest versions)
-    // and break when version matches requirements
-    let mut dependency_edges = vec![];
-    for dep in db_dependencies {
-        let Ok(requirement) = VersionReq::parse(&dep.required_semver) else {
-            continue;
-        };
+    // Crates none of whose version nums are valid semver, ordered by crate_id.
+    // Nothing can depend on them.
+    pub fn crates_without_parseable_versions(&self) -> Vec<(i32, String)> {
+        self.versions_by_crate
+            .iter()
+            .filter(|(_, versions)| versions.is_empty())
+            .map(|(crate_id, _)| {
+                (
+                    *crate_id,
+                    self.crate_names.get(crate_id).cloned().unwrap_or_default(),
+                )
+            })
+            .sorted()
+            .collect()
+    }
+
+    // Returns (crate_id, version_id) pairs, ordered by crate_id.
+    // First is the earliest published version (ties by id), backported patch releases don't replace it.
+    // Latest is the highest non-yanked stable one like crates.io shows it (falling back to pre-releases,
+    // then yanked versions), its id is only compared when no version num parses.
+    pub fn first_or_latest_versions(&self, latest: bool) -> Vec<(i32, i32)> {
+        self.first_and_latest_by_crate
+            .iter()
+            .map(|(crate_id, ((_, first_id), latest_id))| {
+                if !latest {
+                    return (*crate_id, *first_id);
+                }
+                // Same preference as resolve_with, the highest version wins among equally preferred ones
+                let pick = self
+                    .versions_by_crate
+                    .get(crate_id)
+                    .into_iter()
+                    .flatten()
+                    .rev()
+                    .min_by_key(|s| {
+                        (s.yanked as usize) * 2 + (!s.parsed_version.pre.is_empty() as usize)
+                    });
+                (*crate_id, pick.map_or(*latest_id, |s| s.id))
+            })
+            .sorted()
+            .collect()
+    }
+
+    pub fn resolve(
+        &self,
+        dep: &CargoDependenciesDBResponse,
+    ) -> Result<CargoDependencyRGEdgeBuilder, UnresolvedDependency> {
+        self.resolve_with(dep, ResolutionStrategy::Maximal)
+    }
 
-        let mut best_possible_pick: Option<&CargoCrateVersionDBResponse> = None;
-        let all_possible_picks = version_hashmap.get(&dep.to_crate_id).unwrap();
-        for possible_pick in all_possible_picks.iter().rev() {
-            if requirement.matches(&possible_pick.parsed_version) {
-                best_possible_pick = Some(possible_pick.original_crate_version);
+    // Connects the best possible match, following https://doc.rust-lang.org/cargo/reference/resolver.html#pre-releases
+    // - `VersionReq::matches` only lets a pre-release through when the requirement itself
+    //   mentions a pre-release on the same major.minor.patch (same as Cargo)
+    // - the highest (lowest with ResolutionStrategy::Minimal) matching stable version wins,
+    //   pre-releases are picked only when no stable version matches
+    // - yanked versions are picked only when nothing else matches (like Cargo does with a lockfile pointing to them)
+    // - with ResolutionStrategy::AsOfPublishDate, only versions published before the depending one are considered
+    //   (nothing is when either publish date is unknown), yanked states are today's
+    // Going from top to bottom (bottom to top for Minimal)
+    // and break on the first non-yanked stable version that matches requirements
+    pub fn resolve_with(
+        &self,
+        dep: &CargoDependenciesDBResponse,
+        strategy: ResolutionStrategy,
+    ) -> Result<CargoDependencyRGEdgeBuilder, UnresolvedDependency> {
+        let to_crate_name = self.crate_names.get(&dep.to_crate_id);
+        let unresolved = |reason| UnresolvedDependency::new(dep, to_crate_name.cloned(), reason);
+
+        let requirement =
+            VersionReq::parse(&dep.required_semver).map_err(|err| UnresolvedDependency {
+                error: Some(err.to_string()),
+                ..unresolved(UnresolvedReason::BadRequirement)
+            })?;
+
+        if to_crate_name.is_none() {
+            return Err(unresolved(UnresolvedReason::UnknownCrate));
+        }
+        let all_possible_picks = match self.versions_by_crate.get(&dep.to_crate_id) {
+            Some(all_possible_picks) if !all_possible_picks.is_empty() => all_possible_picks,
+            _ => return Err(unresolved(UnresolvedReason::UnparsableVersionNum)),
+        };
+        // Indexed by preference: stable, pre-release, yanked stable, yanked pre-release
+        let mut best_picks: [Option<&VersionCacher>; 4] = [None; 4];
+        let possible_picks: Box<dyn Iterator<Item = &VersionCacher>> = match strategy {
+            ResolutionStrategy::Maximal => Box::new(all_possible_picks.iter().rev()),
+            ResolutionStrategy::Minimal => Box::new(all_possible_picks.iter()),
+            ResolutionStrategy::AsOfPublishDate => {
+                let published_before = self
+                    .created_at_by_version
+                    .get(&dep.from_version_id)
+                    .copied();
+                Box::new(all_possible_picks.iter().rev().filter(move |s| {
+                    match (s.created_at, published_before) {
+                        (Some(created_at), Some(published_before)) => created_at < published_before,
+                        _ => false,
+                    }
+                }))
+            }
+        };
+        for possible_pick in possible_picks {
+            if !requirement.matches(&possible_pick.parsed_version) {
+                continue;
+            }
+            let preference = (possible_pick.yanked as usize) * 2
+                + (!possible_pick.parsed_version.pre.is_empty() as usize);
+            best_picks[preference] = best_picks[preference].or(Some(possible_pick));
+            if preference == 0 {
                 break;
             }
         }
+        let pick = best_picks
+            .into_iter()
+            .flatten()
+            .next()
+            .ok_or_else(|| unresolved(UnresolvedReason::NoMatchingVersion))?;
 
-        if let Some(pick) = best_possible_pick {
-            let mut features_to_include = dep.features.clone();
-            if dep.default_features {
-                features_to_include.push("default".to_owned());
-            }
+        let mut features_to_include = dep.features.clone();
+        if dep.default_features {
+            features_to_include.push("default".to_owned());
+        }
 
-            dependency_edges.push(CargoDependencyRGEdgeBuilder {
+        Ok(CargoDependencyRGEdgeBuilder {
+            from_version_id: dep.from_version_id,
+            to_version_id: pick.id,
+            name: self.dependency_name(dep),
+            required_semver: dep.required_semver.clone(),
+            optional: dep.optional,
+            with_features: features_to_include,
+            target: dep.target.clone(),
+            kind: dep.kind.clone(),
+        })
+    }
+
+    // Every version the requirement matches (yanked and pre-releases included, with the same
+    // pre-release rules as resolve), ordered by semver. Empty when the dependency can't be resolved at all.
+    pub fn candidates(
+        &self,
+        dep: &CargoDependenciesDBResponse,
+    ) -> Vec<CargoCandidateRGEdgeBuilder> {
+        let (Ok(requirement), Some(all_possible_picks)) = (
+            VersionReq::parse(&dep.required_semver),
+            self.versions_by_crate.get(&dep.to_crate_id),
+        ) else {
+            return vec![];
+        };
+
+        all_possible_picks
+            .iter()
+            .filter(|s| requirement.matches(&s.parsed_version))
+            .map(|s| CargoCandidateRGEdgeBuilder {
                 from_version_id: dep.from_version_id,
-                to_version_id: pick.id,
+                to_version_id: s.id,
+                name: self.dependency_name(dep),
                 required_semver: dep.required_semver.clone(),
-                optional: dep.optional,
-                with_features: features_to_include,
                 target: dep.target.clone(),
                 kind: dep.kind.clone(),
-            });
-        }
+            })
+            .collect()
     }
 
-    dependency_edges
+    // Name the dependency is known by inside of the depending crate
+    fn dependency_name(&self, dep: &CargoDependenciesDBResponse) -> String {
+        dep.explicit_name.clone().unwrap_or_else(|| {
+            self.crate_names
+                .get(&dep.to_crate_id)
+                .cloned()
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConnectedDependencies {
+    pub edges: Vec<CargoDependencyRGEdgeBuilder>,
+    // Every dependency that didn't make it into `edges` and why
+    pub report: UnresolvedDependencyReport,
+}
+
+pub fn gen_candidate_redis_graph_link_query(
+    candidates: &[CargoCandidateRGEdgeBuilder],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        candidates.iter().map(|s| {
+            format!(
+                "[{}, {}, {}, {}, {}, {}]",
+                s.from_version_id,
+                s.to_version_id,
+                json!(s.target),
+                s.kind,
+                json!(s.name),
+                json!(s.required_semver),
+            )
+        }).collect(),
+        Some("MATCH (cv_from:CargoCrateVersion {id: map[0]}), (cv_to:CargoCrateVersion {id: map[1]}) CREATE (cv_from)-[:CAN_RESOLVE_TO {target: map[2], kind: map[3], name: map[4], required_semver: map[5]}]->(cv_to)")
+    )
+}
+
+pub fn gen_remove_candidate_redis_graph_link_query(
+    candidates: &[CargoCandidateRGEdgeBuilder],
+    chunk_size: usize,
+) -> anyhow::Result<Vec<String>> {
+    gen_redis_creation_command(
+        chunk_size,
+        candidates.iter().map(|s| {
+            format!(
+                "[{}, {}, {}, {}, {}, {}]",
+                s.from_version_id,
+                s.to_version_id,
+                json!(s.target.clone().unwrap_or_default()),
+                s.kind,
+                json!(s.name),
+                json!(s.required_semver),
+            )
+        }).collect(),
+        Some("MATCH (cv_from:CargoCrateVersion {id: map[0]})-[c:CAN_RESOLVE_TO]->(cv_to:CargoCrateVersion {id: map[1]}) WHERE coalesce(c.target, '') = map[2] AND c.kind = map[3] AND c.name = map[4] AND c.required_semver = map[5] DELETE c")
+    )
+}
+
+pub fn connect_db_dependencies(
+    db_crate_versions: &[CargoCrateVersionDBResponse],
+    db_dependencies: &[CargoDependenciesDBResponse],
+    strategy: ResolutionStrategy,
+) -> ConnectedDependencies {
+    let version_cache = VersionCache::new(db_crate_versions);
+
+    let mut connected = ConnectedDependencies::default();
+    for dep in db_dependencies {
+        connected.edges.extend(
+            connected
+                .report
+                .record(version_cache.resolve_with(dep, strategy)),
+        );
+    }
+    connected
 }
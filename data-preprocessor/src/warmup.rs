@@ -0,0 +1,102 @@
+use futures::{stream, StreamExt, TryStreamExt};
+use redis::aio::MultiplexedConnection;
+use redis_graph::AsyncGraphCommands;
+
+use crate::constants::{REDIS_INSERTION_CHUNK_SIZE, WARM_UP_BATCH_SIZE};
+
+// Indexed (label, property) pairs the imports and traversals look nodes up by
+pub const INDEXED_PROPERTIES: &[(&str, &str)] = &[
+    ("CargoUser", "id"),
+    ("CargoTeam", "id"),
+    ("CargoCrate", "id"),
+    ("CargoCategory", "id"),
+    ("CargoKeyword", "id"),
+    ("CargoCrateVersion", "id"),
+    ("CargoFeature", "version_id"),
+];
+
+#[derive(Debug, Clone)]
+pub struct WarmUpProgress {
+    pub label: &'static str,
+    pub warmed: usize,
+    pub total: usize,
+}
+
+// Looks up every existing node through its index. Ids are paged out of the graph (they don't have to be dense),
+// split into batches and up to `concurrency` batches are queried at the same time.
+pub async fn warm_up_indexes<F>(
+    redis_conn: &MultiplexedConnection,
+    graph_name: &str,
+    concurrency: usize,
+    mut on_progress: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&WarmUpProgress),
+{
+    for (label, property) in INDEXED_PROPERTIES.iter().copied() {
+        let mut conn = redis_conn.clone();
+        let total = conn
+            .graph_ro_query(
+                graph_name,
+                format!("match (s:{label}) return count(s) as total"),
+            )
+            .await?
+            .data
+            .first()
+            .and_then(|s| s.get_scalar::<usize>("total"))
+            .unwrap_or(0);
+
+        let mut progress = WarmUpProgress {
+            label,
+            warmed: 0,
+            total,
+        };
+        on_progress(&progress);
+
+        let mut last_value = i32::MIN;
+        loop {
+            let values: Vec<i32> = conn
+                .graph_ro_query(
+                    graph_name,
+                    format!(
+                        "match (s:{label}) where s.{property} > {last_value} return distinct s.{property} as value order by value limit {REDIS_INSERTION_CHUNK_SIZE}"
+                    ),
+                )
+                .await?
+                .data
+                .iter()
+                .filter_map(|s| s.get_scalar::<i32>("value"))
+                .collect();
+            let Some(max_value) = values.last() else {
+                break;
+            };
+            last_value = *max_value;
+
+            let warmed: Vec<usize> = stream::iter(values.chunks(WARM_UP_BATCH_SIZE))
+                .map(|batch| {
+                    let mut conn = redis_conn.clone();
+                    let query = format!(
+                        "unwind {batch:?} as value match (s:{label} {{{property}: value}}) return count(s) as warmed"
+                    );
+                    async move {
+                        let result = conn.graph_ro_query(graph_name, query).await?;
+                        anyhow::Ok(
+                            result
+                                .data
+                                .first()
+                                .and_then(|s| s.get_scalar::<usize>("warmed"))
+                                .unwrap_or(0),
+                        )
+                    }
+                })
+                .buffer_unordered(concurrency.max(1))
+                .try_collect()
+                .await?;
+
+            progress.warmed += warmed.iter().sum::<usize>();
+            on_progress(&progress);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use common::create_fixture_dump;
+use std::{
+    assert_eq,
+    collections::{BTreeSet, HashMap},
+};
+
+use data_preprocessor::bulk_export::export_bulk_csv;
+use data_preprocessor::pipeline::{run_import, ImportMode, ImportOptions, IngestionBudget};
+use data_preprocessor::source::DataSource;
+
+#[tokio::test]
+async fn graph_is_exported_for_bulk_loader() {
+    // serde_json enables serde/std through an optional dependency
+    let dump_path = create_fixture_dump(
+        "bulk-export",
+        &[
+            (
+                "versions",
+                "crate_id,created_at,downloads,features,id,num,published_by,yanked\n1,2017-04-20 18:45:28.474245,10,{},1,1.0.0,1,f\n2,,0,\"{\"\"default\"\": [\"\"std\"\"], \"\"std\"\": [\"\"serde/std\"\"]}\",2,1.0.0,,f\n",
+            ),
+            (
+                "version_downloads",
+                "date,downloads,version_id\n2023-05-30,3,1\n2023-05-31,2,1\n",
+            ),
+            (
+                "dependencies",
+                "crate_id,default_features,explicit_name,features,id,kind,optional,req,target,version_id\n1,t,,{},1,0,t,^1.0,,2\n",
+            ),
+        ],
+    );
+    let out_dir =
+        std::env::temp_dir().join(format!("deps-graph-bulk-export-{}", std::process::id()));
+
+    let counts = export_bulk_csv(
+        &DataSource::DbDump(dump_path.clone()),
+        &IngestionBudget {
+            page_size: 1,
+            ..Default::default()
+        },
+        &ImportOptions::default(),
+        &out_dir,
+    )
+    .await
+    .unwrap();
+    assert_eq!(counts.crate_versions, 2);
+
+    let read = |name: &str| std::fs::read_to_string(out_dir.join(format!("{name}.csv"))).unwrap();
+    assert_eq!(
+        read("CargoCrateVersion"),
+        "id:ID(CargoCrateVersion),num:STRING,crate_name:STRING,yanked:BOOLEAN,description:STRING,repository:STRING,documentation:STRING,homepage:STRING,created_at:INT,downloads:INT,recent_downloads:INT\n1,1.0.0,serde,false,,,,,1492713928,10,5\n2,1.0.0,serde_json,false,,,,,,0,0\n"
+    );
+    assert_eq!(
+        read("CargoCrate"),
+        "id:ID(CargoCrate),name:STRING,description:STRING,repository:STRING,documentation:STRING,homepage:STRING,downloads:INT,recent_downloads:INT\n1,serde,,,,,125,5\n2,serde_json,,,,,7,0\n"
+    );
+    assert_eq!(
+        read("CargoCategory"),
+        "id:ID(CargoCategory),name:STRING,slug:STRING,description:STRING\n1,Encoding,encoding,Encoding and/or decoding data\n"
+    );
+    assert_eq!(
+        read("IN_CATEGORY"),
+        ":START_ID(CargoCrate),:END_ID(CargoCategory)\n1,1\n2,1\n"
+    );
+    assert_eq!(
+        read("CargoTeam"),
+        "id:ID(CargoTeam),login:STRING,name:STRING,avatar:STRING\n1,github:serde-rs:publish,Publish,\n"
+    );
+    assert_eq!(
+        read("OWNS"),
+        ":START_ID(CargoUser),:END_ID(CargoCrate)\n1,1\n1,2\n"
+    );
+    assert_eq!(
+        read("OWNS-teams"),
+        ":START_ID(CargoTeam),:END_ID(CargoCrate)\n1,1\n"
+    );
+    assert_eq!(
+        read("PUBLISHED"),
+        ":START_ID(CargoUser),:END_ID(CargoCrateVersion)\n1,1\n"
+    );
+    assert_eq!(
+        read("LATEST_VERSION"),
+        ":START_ID(CargoCrate),:END_ID(CargoCrateVersion)\n1,1\n2,2\n"
+    );
+    assert_eq!(
+        read("CargoFeature"),
+        ":ID(CargoFeature),version_id:INT,name:STRING,enables:ARRAY,activates:ARRAY\n1,2,default,\"[\"\"std\"\"]\",[]\n2,2,std,\"[\"\"serde/std\"\"]\",\"[\"\"serde\"\"]\"\n"
+    );
+    assert_eq!(
+        read("ENABLES"),
+        ":START_ID(CargoFeature),:END_ID(CargoFeature)\n1,2\n"
+    );
+    assert_eq!(
+        read("ENABLES-dependencies"),
+        ":START_ID(CargoFeature),:END_ID(CargoCrateVersion)\n2,1\n"
+    );
+    assert!(read("DEPENDS_ON").ends_with("\n2,1,true,\"[\"\"default\"\"]\",,0,serde,^1.0\n"));
+
+    std::fs::remove_dir_all(out_dir).unwrap();
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+// Rows of an `unwind [...] as map` query
+fn unwind_rows(query: &str) -> Vec<serde_json::Value> {
+    let rows = query
+        .strip_prefix("unwind ")
+        .and_then(|s| s.split_once(" as map"))
+        .unwrap()
+        .0;
+    serde_json::from_str(rows).unwrap()
+}
+
+#[tokio::test]
+async fn features_activate_the_same_dependencies_as_with_queries() {
+    // "serde" is declared as a feature as well, `default` enables that feature and not the dependency directly
+    let dump_path = create_fixture_dump(
+        "bulk-export-feature-links",
+        &[
+            (
+                "versions",
+                "crate_id,features,id,num,published_by,yanked\n1,{},1,1.0.0,1,f\n2,\"{\"\"default\"\": [\"\"std\"\", \"\"serde\"\"], \"\"serde\"\": [\"\"dep:serde\"\"], \"\"std\"\": [\"\"serde?/std\"\"], \"\"derive\"\": [\"\"serde/derive\"\"]}\",2,1.0.0,,f\n",
+            ),
+            (
+                "dependencies",
+                "crate_id,default_features,explicit_name,features,id,kind,optional,req,target,version_id\n1,t,,{},1,0,t,^1.0,,2\n",
+            ),
+        ],
+    );
+    let source = DataSource::DbDump(dump_path.clone());
+    let budget = IngestionBudget::default();
+
+    let mut queries: Vec<String> = vec![];
+    run_import(
+        &source,
+        &ImportMode::Full,
+        &budget,
+        &ImportOptions::default(),
+        &mut queries,
+    )
+    .await
+    .unwrap();
+    // Same as the database does with the link query: `map[2] IN f.activates`
+    let feature_rows = unwind_rows(queries.iter().find(|s| s.contains("HAS_FEATURE")).unwrap());
+    let link_rows = unwind_rows(
+        queries
+            .iter()
+            .find(|s| s.contains("MERGE (f)-[:ENABLES]->(cv_to)"))
+            .unwrap(),
+    );
+    let linked_with_queries: BTreeSet<String> = feature_rows
+        .iter()
+        .flat_map(|feature| {
+            link_rows
+                .iter()
+                .filter(|link| {
+                    link[0] == feature[0] && feature[3].as_array().unwrap().contains(&link[2])
+                })
+                .map(|link| {
+                    format!(
+                        "{}:{},{}",
+                        feature[0],
+                        feature[1].as_str().unwrap(),
+                        link[1]
+                    )
+                })
+        })
+        .collect();
+
+    let out_dir = std::env::temp_dir().join(format!(
+        "deps-graph-bulk-export-feature-links-{}",
+        std::process::id()
+    ));
+    export_bulk_csv(&source, &budget, &ImportOptions::default(), &out_dir)
+        .await
+        .unwrap();
+    // Feature ids are running numbers, named `<version id>:<name>` like above
+    let features = std::fs::read_to_string(out_dir.join("CargoFeature.csv")).unwrap();
+    let feature_names: HashMap<&str, String> = features
+        .lines()
+        .skip(1)
+        .map(|s| {
+            let columns: Vec<&str> = s.splitn(4, ',').collect();
+            (columns[0], format!("{}:{}", columns[1], columns[2]))
+        })
+        .collect();
+    let exported = std::fs::read_to_string(out_dir.join("ENABLES-dependencies.csv")).unwrap();
+    let linked_in_bulk: BTreeSet<String> = exported
+        .lines()
+        .skip(1)
+        .map(|s| {
+            let (feature_id, version_id) = s.split_once(',').unwrap();
+            format!("{},{version_id}", feature_names[feature_id])
+        })
+        .collect();
+
+    assert_eq!(
+        linked_with_queries,
+        BTreeSet::from(["2:derive,1".to_owned(), "2:serde,1".to_owned()])
+    );
+    assert_eq!(linked_with_queries, linked_in_bulk);
+
+    std::fs::remove_dir_all(out_dir).unwrap();
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[tokio::test]
+async fn id_columns_are_integers() {
+    // Loaded with `--id-type INTEGER`, the API looks nodes up by their numeric ids
+    let dump_path = create_fixture_dump("bulk-export-ids", &[]);
+    let out_dir =
+        std::env::temp_dir().join(format!("deps-graph-bulk-export-ids-{}", std::process::id()));
+    export_bulk_csv(
+        &DataSource::DbDump(dump_path.clone()),
+        &IngestionBudget::default(),
+        &ImportOptions {
+            candidate_edges: true,
+            minimal_versions: true,
+            as_of_publish_date: true,
+        },
+        &out_dir,
+    )
+    .await
+    .unwrap();
+
+    let mut checked_files = 0;
+    for entry in std::fs::read_dir(&out_dir).unwrap() {
+        let mut reader = csv::Reader::from_path(entry.unwrap().path()).unwrap();
+        let id_columns: Vec<usize> = reader
+            .headers()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.contains("ID("))
+            .map(|(i, _)| i)
+            .collect();
+        assert!(!id_columns.is_empty());
+        for record in reader.records() {
+            let record = record.unwrap();
+            for i in id_columns.iter() {
+                assert!(record[*i].parse::<i64>().is_ok(), "{record:?}");
+            }
+        }
+        checked_files += 1;
+    }
+    assert!(checked_files > 20);
+
+    std::fs::remove_dir_all(out_dir).unwrap();
+    std::fs::remove_file(dump_path).unwrap();
+}
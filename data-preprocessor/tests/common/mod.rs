@@ -0,0 +1,151 @@
+// Shared by every test binary, each one only uses part of it
+#![allow(dead_code, unused_macros)]
+
+use std::{fs::File, path::PathBuf};
+
+use flate2::{write::GzEncoder, Compression};
+
+// Builds a tiny db-dump.tar.gz with the same layout crates.io uses ("<timestamp>/data/<table>.csv")
+pub fn create_test_dump(name: &str, tables: &[(&str, &str)]) -> PathBuf {
+    let dump_path =
+        std::env::temp_dir().join(format!("deps-graph-{}-{}.tar.gz", name, std::process::id()));
+    let mut archive = tar::Builder::new(GzEncoder::new(
+        File::create(&dump_path).unwrap(),
+        Compression::fast(),
+    ));
+    for (table, content) in tables {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive
+            .append_data(
+                &mut header,
+                format!("2023-06-01-020046/data/{table}.csv"),
+                content.as_bytes(),
+            )
+            .unwrap();
+    }
+    archive.into_inner().unwrap().finish().unwrap();
+
+    dump_path
+}
+
+// Small crates.io dump: serde (1.0.0, 1.0.1) and serde_json 1.0.0 depending on serde ^1.0,
+// with an owner, a team, a category and a keyword
+pub const FIXTURE_TABLES: &[(&str, &str)] = &[
+    ("users", "gh_avatar,gh_id,gh_login,id,name\n,1,octocat,1,\n"),
+    (
+        "teams",
+        "avatar,github_id,id,login,name,org_id\n,2,1,github:serde-rs:publish,Publish,3\n",
+    ),
+    // serde's downloads include the ones of a since deleted version
+    (
+        "crates",
+        "downloads,id,name\n125,1,serde\n7,2,serde_json\n",
+    ),
+    (
+        "crate_owners",
+        "crate_id,created_at,created_by,owner_id,owner_kind\n1,2017-01-17 19:13:05.112025,1,1,0\n1,2017-01-17 19:13:05.112025,1,1,1\n2,2017-01-17 19:13:05.112025,1,1,0\n",
+    ),
+    (
+        "categories",
+        "category,crates_cnt,created_at,description,id,path,slug\nEncoding,2,2017-01-17 19:13:05.112025,Encoding and/or decoding data,1,root.encoding,encoding\n",
+    ),
+    ("crates_categories", "category_id,crate_id\n1,1\n1,2\n"),
+    (
+        "keywords",
+        "crates_cnt,created_at,id,keyword\n1,2017-01-17 19:13:05.112025,1,serde\n",
+    ),
+    ("crates_keywords", "crate_id,keyword_id\n1,1\n"),
+    (
+        "versions",
+        "crate_id,downloads,features,id,num,published_by,yanked\n1,100,\"{\"\"default\"\": [\"\"std\"\"], \"\"std\"\": []}\",1,1.0.0,1,f\n2,7,{},2,1.0.0,1,f\n1,20,{},3,1.0.1,1,f\n",
+    ),
+    (
+        "version_downloads",
+        "date,downloads,version_id\n2023-05-30,3,1\n2023-05-31,2,1\n2023-05-31,4,3\n",
+    ),
+    (
+        "dependencies",
+        "crate_id,default_features,explicit_name,features,id,kind,optional,req,target,version_id\n1,t,,{},1,0,f,^1.0,,2\n",
+    ),
+];
+
+// FIXTURE_TABLES, with some of the tables replaced
+pub fn create_fixture_dump(name: &str, replaced_tables: &[(&str, &str)]) -> PathBuf {
+    let tables: Vec<(&str, &str)> = FIXTURE_TABLES
+        .iter()
+        .map(|(table, content)| {
+            replaced_tables
+                .iter()
+                .find(|(replaced, _)| replaced == table)
+                .copied()
+                .unwrap_or((table, content))
+        })
+        .collect();
+    create_test_dump(name, &tables)
+}
+
+// Important for versions: id, create_id, num, yanked
+// Important for depenedencies: required_semver, to_crate_id
+macro_rules! quick_crate_version {
+    ($id:expr, $crate_id:expr, $num:expr) => {
+        quick_crate_version![$id, $crate_id, $num, false]
+    };
+    ($id:expr, $crate_id:expr, $num:expr, $yanked:expr) => {
+        data_preprocessor::models::CargoCrateVersionDBResponse {
+            id: $id,
+            crate_id: $crate_id,
+            num: $num.into(),
+            features: sqlx::types::Json(std::collections::HashMap::new()),
+            published_by: None,
+            yanked: $yanked,
+            created_at: None,
+            downloads: 0,
+            crate_name: "".into(),
+            description: None,
+            repository: None,
+            documentation: None,
+            homepage: None,
+        }
+    };
+}
+
+// Depends from version 1, unless told otherwise
+macro_rules! quick_dependency {
+    ($to:expr, $req:expr) => {
+        quick_dependency![1, $to, $req]
+    };
+    ($from:expr, $to:expr, $req:expr) => {
+        data_preprocessor::models::CargoDependenciesDBResponse {
+            from_version_id: $from,
+            to_crate_id: $to,
+            explicit_name: None,
+            required_semver: $req.into(),
+            optional: false,
+            default_features: false,
+            features: Vec::new(),
+            target: None,
+            kind: data_preprocessor::models::CargoDependencyKind::Normal,
+        }
+    };
+}
+
+macro_rules! quick_edge {
+    ($to:expr, $req:expr) => {
+        quick_edge![1, $to, $req]
+    };
+    ($from:expr, $to:expr, $req:expr) => {
+        data_preprocessor::models::CargoDependencyRGEdgeBuilder {
+            from_version_id: $from,
+            to_version_id: $to,
+            name: "".into(),
+            required_semver: $req.into(),
+            optional: false,
+            with_features: Vec::new(),
+            target: None,
+            kind: data_preprocessor::models::CargoDependencyKind::Normal,
+        }
+    };
+}
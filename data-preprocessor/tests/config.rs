@@ -0,0 +1,73 @@
+use std::{assert_eq, path::Path};
+
+use data_preprocessor::config::{Config, SinkKind};
+use data_preprocessor::constants::{CARGO_GRAPH_NAME, DEFAULT_GRAPH_URL};
+
+#[test]
+fn example_config_matches_defaults() {
+    let config = Config::from_file(Path::new("deps-graph.example.toml")).unwrap();
+    assert_eq!(config, Config::default());
+}
+
+#[test]
+fn env_overrides_config_file() {
+    let config_path =
+        std::env::temp_dir().join(format!("deps-graph-config-{}.toml", std::process::id()));
+    std::fs::write(&config_path, "[graph]\nname = \"staging_graph\"\n").unwrap();
+
+    let mut config = Config::from_file(&config_path).unwrap();
+    assert_eq!(config.graph.url, DEFAULT_GRAPH_URL);
+    assert_eq!(config.import, Config::default().import);
+
+    config
+        .apply_env_overrides([
+            ("PATH".to_owned(), "/usr/bin".to_owned()),
+            (
+                "DEPS_GRAPH_GRAPH_URL".to_owned(),
+                "redis://graph.internal:6379/".to_owned(),
+            ),
+            ("DEPS_GRAPH_CHUNK_SIZE".to_owned(), "1000".to_owned()),
+            ("DEPS_GRAPH_GRAPH_SINK".to_owned(), "falkordb".to_owned()),
+        ])
+        .unwrap();
+    assert_eq!(config.graph.url, "redis://graph.internal:6379/");
+    assert_eq!(config.graph.name, "staging_graph");
+    assert_eq!(config.graph.sink, SinkKind::FalkorDb);
+    assert_eq!(config.import.chunk_size, 1000);
+    assert_eq!(config.import.budget().page_size, 1000);
+    config.validate().unwrap();
+
+    assert!(config
+        .apply_env_overrides([("DEPS_GRAPH_CHUNK_SIZE".to_owned(), "a lot".to_owned())])
+        .is_err());
+    assert!(config
+        .apply_env_overrides([("DEPS_GRAPH_GRAPH_SINK".to_owned(), "neo4j".to_owned())])
+        .is_err());
+    assert!(config
+        .apply_env_overrides([(
+            "DEPS_GRAPH_GRAPH_NAM".to_owned(),
+            CARGO_GRAPH_NAME.to_owned()
+        )])
+        .is_err());
+
+    std::fs::write(&config_path, "[graph]\nnmae = \"typo\"\n").unwrap();
+    assert!(Config::from_file(&config_path).is_err());
+
+    std::fs::remove_file(config_path).unwrap();
+}
+
+#[test]
+fn sink_is_read_from_config_file() {
+    let config_path =
+        std::env::temp_dir().join(format!("deps-graph-sink-{}.toml", std::process::id()));
+    std::fs::write(&config_path, "[graph]\nsink = \"files\"\n").unwrap();
+    assert_eq!(
+        Config::from_file(&config_path).unwrap().graph.sink,
+        SinkKind::Files
+    );
+
+    std::fs::write(&config_path, "[graph]\nsink = \"neo4j\"\n").unwrap();
+    assert!(Config::from_file(&config_path).is_err());
+
+    std::fs::remove_file(config_path).unwrap();
+}
@@ -0,0 +1,70 @@
+use std::assert_eq;
+
+use data_preprocessor::cypher_files::{
+    count_unwind_rows, read_cypher_manifest, replay_cypher_files, CypherFileWriter,
+};
+use data_preprocessor::sink::{CypherFilesSink, GraphSink};
+
+#[test]
+fn unwind_rows_are_counted() {
+    assert_eq!(count_unwind_rows("unwind [] as map create (:A)"), 0);
+    assert_eq!(count_unwind_rows("unwind [1,2,3] as map create (:A)"), 3);
+    assert_eq!(
+        count_unwind_rows(r#"unwind [[1, "a,]", ["x","y"]],[2, "\"", []]] as map create (:A)"#),
+        2
+    );
+}
+
+#[test]
+fn dry_run_files_are_replayed_in_order() {
+    let dir = std::env::temp_dir().join(format!("deps-graph-cypher-{}", std::process::id()));
+    let queries = [
+        "unwind [[1,\"a\"],[2,\"b\"]] as map create (:A {id: map[0]})",
+        "unwind [[1, 2]] as map MATCH (a:A {id: map[0]}), (b:A {id: map[1]}) CREATE (a)-[:B]->(b)",
+    ];
+
+    let mut writer = CypherFileWriter::create(&dir, true, "test".into(), 1).unwrap();
+    for query in queries {
+        writer.write(query).unwrap();
+    }
+    let manifest = writer.finish().unwrap();
+    assert_eq!(read_cypher_manifest(&dir).unwrap(), manifest);
+    assert_eq!(
+        manifest.queries.iter().map(|s| s.rows).collect::<Vec<_>>(),
+        vec![2, 1]
+    );
+
+    let mut replayed = vec![];
+    replay_cypher_files(&dir, &manifest, |query| {
+        replayed.push(query);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(replayed, queries);
+
+    // Output of a previous dry-run doesn't leak into the next one
+    let writer = CypherFileWriter::create(&dir, true, "test".into(), 1).unwrap();
+    assert!(read_cypher_manifest(&dir).is_err());
+    assert!(writer.finish().unwrap().queries.is_empty());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn files_sink_writes_manifest_on_finish() {
+    let dir = std::env::temp_dir().join(format!("deps-graph-cypher-sink-{}", std::process::id()));
+
+    let mut sink = CypherFilesSink::create(&dir, false, "test".into(), 1).unwrap();
+    sink.execute("unwind [1] as map create (:A)".into())
+        .unwrap();
+    assert!(sink.manifest().is_none());
+    sink.finish().unwrap();
+
+    let manifest = read_cypher_manifest(&dir).unwrap();
+    assert_eq!(sink.manifest(), Some(&manifest));
+    assert!(!manifest.full_import);
+    assert_eq!(manifest.queries.len(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use common::create_test_dump;
+use std::{assert_eq, collections::HashMap};
+
+use data_preprocessor::dump::{
+    missing_dump_tables, parse_pg_array, read_crate_versions_from_dump, read_crates_from_dump,
+    read_raw_dependencies_from_dump, read_users_from_dump,
+};
+use data_preprocessor::models::CargoDependencyKind;
+
+#[test]
+fn pg_array_parsing() {
+    assert_eq!(parse_pg_array("{}"), Vec::<String>::new());
+    assert_eq!(parse_pg_array("{std}"), vec!["std"]);
+    assert_eq!(parse_pg_array("{std,derive}"), vec!["std", "derive"]);
+    assert_eq!(
+        parse_pg_array(r#"{"with space","with,comma",plain}"#),
+        vec!["with space", "with,comma", "plain"]
+    );
+}
+
+#[test]
+fn read_tables_from_dump() {
+    let dump_path = create_test_dump(
+        "read-tables",
+        &[
+            (
+                "users",
+                "gh_avatar,gh_id,gh_login,id,name\nhttps://avatar,1,octocat,1,Octo Cat\n,2,nobody,2,\n",
+            ),
+            (
+                "crates",
+                "description,documentation,downloads,homepage,id,name,repository\nA serialization framework,https://docs.rs/serde,10,https://serde.rs,1,serde,https://github.com/serde-rs/serde\n,,5,,2,serde_json,\n",
+            ),
+            (
+                "versions",
+                "crate_id,features,id,num,published_by,yanked\n1,\"{\"\"std\"\": []}\",2,1.0.1,1,f\n1,{},1,1.0.0,,f\n",
+            ),
+            (
+                "dependencies",
+                "crate_id,default_features,explicit_name,features,id,kind,optional,req,target,version_id\n1,t,,{std},1,0,f,^1.0,,2\n1,f,serde1,{},2,2,t,=1.0.0,cfg(unix),1\n",
+            ),
+        ],
+    );
+
+    let mut users = vec![];
+    read_users_from_dump(&dump_path, |user| {
+        users.push(user);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0].gh_username, "octocat");
+    assert_eq!(users[0].preferred_name.as_deref(), Some("Octo Cat"));
+    assert_eq!(users[1].gh_avatar, None);
+
+    let mut crates = vec![];
+    read_crates_from_dump(&dump_path, |crate_row| {
+        crates.push(crate_row);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(crates.len(), 2);
+    assert_eq!(crates[0].homepage.as_deref(), Some("https://serde.rs"));
+    assert_eq!(crates[1].description, None);
+
+    let crates_by_id: HashMap<_, _> = crates.into_iter().map(|s| (s.id, s)).collect();
+    let mut crate_versions = vec![];
+    read_crate_versions_from_dump(&dump_path, &crates_by_id, |crate_version| {
+        crate_versions.push(crate_version);
+        Ok(())
+    })
+    .unwrap();
+    // Rows are read in dump order
+    crate_versions.sort_by_key(|s| s.id);
+    assert_eq!(
+        crate_versions.iter().map(|s| s.id).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert_eq!(crate_versions[0].crate_name, "serde");
+    assert_eq!(
+        crate_versions[0].description.as_deref(),
+        Some("A serialization framework")
+    );
+    assert_eq!(
+        crate_versions[0].repository.as_deref(),
+        Some("https://github.com/serde-rs/serde")
+    );
+    assert_eq!(crate_versions[0].published_by, None);
+    assert!(crate_versions[1].features.contains_key("std"));
+
+    let mut dependencies = vec![];
+    read_raw_dependencies_from_dump(&dump_path, |dependency| {
+        dependencies.push(dependency);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(dependencies.len(), 2);
+    assert!(dependencies[0].default_features);
+    assert_eq!(dependencies[0].features, vec!["std"]);
+    assert_eq!(dependencies[0].target, None);
+    assert_eq!(dependencies[0].explicit_name, None);
+    assert!(dependencies[1].optional);
+    assert_eq!(dependencies[1].explicit_name.as_deref(), Some("serde1"));
+    assert_eq!(dependencies[1].kind, CargoDependencyKind::Dev);
+    assert_eq!(dependencies[1].target.as_deref(), Some("cfg(unix)"));
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[test]
+fn missing_tables_are_reported() {
+    let dump_path = create_test_dump(
+        "missing-tables",
+        &[
+            ("users", "id\n"),
+            ("crates", "id\n"),
+            ("categories", "id\n"),
+        ],
+    );
+    assert_eq!(
+        missing_dump_tables(&dump_path).unwrap(),
+        vec![
+            "teams",
+            "crate_owners",
+            "crates_categories",
+            "keywords",
+            "crates_keywords",
+            "versions",
+            "version_downloads",
+            "dependencies"
+        ]
+    );
+    std::fs::remove_file(dump_path).unwrap();
+}
@@ -0,0 +1,188 @@
+#[macro_use]
+mod common;
+
+use common::create_fixture_dump;
+use data_preprocessor::incremental::{
+    diff_candidate_edges, diff_dependency_edges, diff_first_or_latest_versions, ExistingGraphIds,
+};
+use data_preprocessor::pipeline::{run_import, ImportMode, ImportOptions, IngestionBudget};
+use data_preprocessor::source::DataSource;
+use data_preprocessor::unresolved::UnresolvedDependencyReport;
+use data_preprocessor::utils::{ResolutionStrategy, VersionCache};
+use std::{
+    assert_eq,
+    collections::{HashMap, HashSet},
+};
+
+use data_preprocessor::models::{CargoCandidateRGEdgeBuilder, CargoDependencyKind};
+
+#[test]
+fn candidate_edges_delta() {
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![2, 2, "0.1.0"],
+        quick_crate_version![3, 1, "1.1.0"], // new
+        quick_crate_version![4, 1, "2.0.0"], // new
+    ];
+    let existing_version_ids: HashSet<i32> = HashSet::from([1, 2]);
+    let db_dependencies = vec![quick_dependency![2, 1, "^1.0.0"]];
+
+    let old_version_cache = VersionCache::new(
+        db_crate_versions
+            .iter()
+            .filter(|s| existing_version_ids.contains(&s.id)),
+    );
+    let new_version_cache = VersionCache::new(&db_crate_versions);
+    assert_eq!(new_version_cache.candidates(&db_dependencies[0]).len(), 2);
+
+    let delta = diff_candidate_edges(
+        &old_version_cache,
+        &new_version_cache,
+        &existing_version_ids,
+        &db_dependencies,
+    );
+    // The already linked 1.0.0 stays, 2.0.0 doesn't match
+    assert!(delta.removed.is_empty());
+    assert_eq!(
+        delta.added,
+        vec![CargoCandidateRGEdgeBuilder {
+            from_version_id: 2,
+            to_version_id: 3,
+            name: "".into(),
+            required_semver: "^1.0.0".into(),
+            target: None,
+            kind: CargoDependencyKind::Normal,
+        }]
+    );
+}
+
+#[test]
+fn dependency_edges_delta() {
+    // Crate 1 is the dependency, crate 2 depends on it
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![2, 2, "0.1.0"],
+        quick_crate_version![3, 1, "1.1.0"], // new
+        quick_crate_version![4, 2, "0.2.0"], // new
+    ];
+    let existing_version_ids: HashSet<i32> = HashSet::from([1, 2]);
+    let db_dependencies = vec![
+        quick_dependency![2, 1, "^1.0.0"],
+        quick_dependency![2, 1, "=1.0.0"],
+        quick_dependency![4, 1, "^1.0.0"],
+    ];
+
+    let old_version_cache = VersionCache::new(
+        db_crate_versions
+            .iter()
+            .filter(|s| existing_version_ids.contains(&s.id)),
+    );
+    let new_version_cache = VersionCache::new(&db_crate_versions);
+
+    let delta = diff_dependency_edges(
+        &old_version_cache,
+        &new_version_cache,
+        &existing_version_ids,
+        &HashSet::new(),
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+        &mut UnresolvedDependencyReport::default(),
+    );
+    assert_eq!(delta.removed, vec![quick_edge![2, 1, "^1.0.0"]]);
+    assert_eq!(
+        delta.added,
+        vec![quick_edge![2, 3, "^1.0.0"], quick_edge![4, 3, "^1.0.0"]]
+    );
+}
+
+#[test]
+fn first_and_latest_versions_delta() {
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![2, 2, "0.1.0"],
+        quick_crate_version![3, 1, "1.1.0"], // new
+        quick_crate_version![4, 3, "0.1.0"], // new, new crate
+    ];
+    let existing_version_ids: HashSet<i32> = HashSet::from([1, 2]);
+    let old_version_cache = VersionCache::new(
+        db_crate_versions
+            .iter()
+            .filter(|s| existing_version_ids.contains(&s.id)),
+    );
+    let new_version_cache = VersionCache::new(&db_crate_versions);
+
+    let first_delta = diff_first_or_latest_versions(
+        &old_version_cache,
+        &new_version_cache,
+        &HashSet::new(),
+        false,
+    );
+    assert_eq!(first_delta.removed_crate_ids, Vec::<i32>::new());
+    assert_eq!(first_delta.added, vec![(3, 4)]);
+
+    let latest_delta = diff_first_or_latest_versions(
+        &old_version_cache,
+        &new_version_cache,
+        &HashSet::new(),
+        true,
+    );
+    assert_eq!(latest_delta.removed_crate_ids, vec![1]);
+    assert_eq!(latest_delta.added, vec![(1, 3), (3, 4)]);
+}
+
+#[tokio::test]
+async fn nodes_deleted_from_the_source_are_removed() {
+    // The graph was imported from an older dump, with a serde_json 2.0.0 (crate 3, version 5)
+    // and a serde 1.1.0 (version 4) that were deleted since. 1.1.0 was serde's latest version
+    // and what serde_json 1.0.0 (version 2) depended on.
+    let dump_path = create_fixture_dump("incremental-deleted", &[]);
+    let existing_ids = ExistingGraphIds {
+        users: HashSet::from([1]),
+        teams: HashSet::from([1]),
+        crates: HashSet::from([1, 2, 3]),
+        categories: HashSet::from([1]),
+        keywords: HashSet::from([1]),
+        crate_versions: HashSet::from([1, 2, 3, 4, 5]),
+        crate_version_counts: HashMap::from([(1, 3), (2, 1), (3, 1)]),
+        ..Default::default()
+    };
+    let mut queries: Vec<String> = vec![];
+    run_import(
+        &DataSource::DbDump(dump_path.clone()),
+        &ImportMode::Incremental(Box::new(existing_ids)),
+        &IngestionBudget::default(),
+        &ImportOptions::default(),
+        &mut queries,
+    )
+    .await
+    .unwrap();
+
+    let removals: Vec<_> = queries
+        .iter()
+        .filter(|s| s.contains("DETACH DELETE"))
+        .collect();
+    assert_eq!(removals.len(), 2);
+    assert!(removals[0].starts_with("unwind [3] as map MATCH (n:CargoCrate {id: map})"));
+    assert!(removals[1].starts_with("unwind [4,5] as map MATCH (cv:CargoCrateVersion"));
+    assert!(removals[1].contains("(cv)-[:HAS_FEATURE]->(f:CargoFeature)"));
+    // Edges to the deleted serde version went with it, they point to 1.0.1 (version 3) again
+    let relinked = |prefix: &str, link: &str| {
+        queries
+            .iter()
+            .any(|s| s.starts_with(prefix) && s.contains(link))
+    };
+    assert!(relinked("unwind [1] as map", "-[l:LATEST_VERSION]->"));
+    assert!(relinked(
+        "unwind [[1, 3]] as map",
+        "CREATE (cc)-[:LATEST_VERSION]->(cv)"
+    ));
+    assert!(relinked(
+        "unwind [[1, 1]] as map",
+        "CREATE (cc)-[:FIRST_VERSION]->(cv)"
+    ));
+    assert!(relinked("unwind [[2, 3,", "CREATE (cv_from)-[:DEPENDS_ON"));
+    // serde_json didn't lose a version that's still listed
+    assert!(!relinked("unwind [[2, 2]] as map", "LATEST_VERSION"));
+
+    std::fs::remove_file(dump_path).unwrap();
+}
@@ -0,0 +1,282 @@
+mod common;
+
+use common::create_fixture_dump;
+use std::{
+    assert_eq,
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use data_preprocessor::incremental::{crate_metadata_hash, ExistingGraphIds};
+use data_preprocessor::pipeline::{
+    run_import, ImportMode, ImportOptions, IngestionBudget, KeptEntries,
+};
+use data_preprocessor::source::DataSource;
+
+async fn collect_queries(
+    dump_path: &Path,
+    import_mode: &ImportMode,
+    options: &ImportOptions,
+) -> Vec<String> {
+    let mut queries: Vec<String> = vec![];
+    run_import(
+        &DataSource::DbDump(dump_path.to_path_buf()),
+        import_mode,
+        &IngestionBudget {
+            page_size: 1,
+            ..Default::default()
+        },
+        options,
+        &mut queries,
+    )
+    .await
+    .unwrap();
+    queries
+}
+
+#[tokio::test]
+async fn full_import_is_flushed_page_by_page() {
+    let dump_path = create_fixture_dump("pipeline-full", &[]);
+    let queries = collect_queries(&dump_path, &ImportMode::Full, &ImportOptions::default()).await;
+
+    // Page size of 1 => one node query per row
+    let crate_version_node_queries = queries
+        .iter()
+        .filter(|s| s.contains("create (:CargoCrateVersion"))
+        .count();
+    assert_eq!(crate_version_node_queries, 3);
+
+    // Dependencies are resolved against every version, not only the ones in the same page
+    let dependency_query_position = queries
+        .iter()
+        .position(|s| s.contains("DEPENDS_ON"))
+        .unwrap();
+    assert!(queries[dependency_query_position].starts_with("unwind [[2, 3,"));
+    assert!(queries[dependency_query_position].contains(r#""serde", "^1.0"]]"#));
+    let last_node_query_position = queries
+        .iter()
+        .rposition(|s| s.contains("create (:CargoCrateVersion"))
+        .unwrap();
+    assert!(last_node_query_position < dependency_query_position);
+
+    // Team owners are linked from their own label
+    assert!(queries.iter().any(
+        |s| s.starts_with("unwind [[1, 1]] as map") && s.contains("CREATE (ct)-[:OWNS]->(cc)")
+    ));
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[tokio::test]
+async fn candidate_edges_are_opt_in() {
+    let dump_path = create_fixture_dump("pipeline-candidates", &[]);
+    let queries = collect_queries(&dump_path, &ImportMode::Full, &ImportOptions::default()).await;
+    assert!(!queries.iter().any(|s| s.contains("CAN_RESOLVE_TO")));
+
+    let options = ImportOptions {
+        candidate_edges: true,
+        ..Default::default()
+    };
+    let queries = collect_queries(&dump_path, &ImportMode::Full, &options).await;
+    let candidate_query = queries
+        .iter()
+        .find(|s| s.contains("CAN_RESOLVE_TO"))
+        .unwrap();
+    // serde 1.0.0 and 1.0.1 both match ^1.0
+    assert!(candidate_query.starts_with(
+        r#"unwind [[2, 1, null, 0, "serde", "^1.0"],[2, 3, null, 0, "serde", "^1.0"]] as map"#
+    ));
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[tokio::test]
+async fn minimal_versions_are_opt_in() {
+    let dump_path = create_fixture_dump("pipeline-minimal-versions", &[]);
+    let queries = collect_queries(&dump_path, &ImportMode::Full, &ImportOptions::default()).await;
+    assert!(!queries.iter().any(|s| s.contains("DEPENDS_ON_MIN")));
+
+    let options = ImportOptions {
+        minimal_versions: true,
+        ..Default::default()
+    };
+    let queries = collect_queries(&dump_path, &ImportMode::Full, &options).await;
+    let minimal_query = queries
+        .iter()
+        .find(|s| s.contains("DEPENDS_ON_MIN"))
+        .unwrap();
+    // serde 1.0.0 instead of 1.0.1
+    assert!(minimal_query.starts_with("unwind [[2, 1,"));
+    // Next to the maximal edges, not instead of them
+    assert_eq!(
+        queries
+            .iter()
+            .filter(|s| s.contains("-[:DEPENDS_ON {"))
+            .count(),
+        1
+    );
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[tokio::test]
+async fn crate_downloads_are_read_and_recent_ones_summed_up() {
+    let dump_path = create_fixture_dump("pipeline-downloads", &[]);
+    let queries = collect_queries(&dump_path, &ImportMode::Full, &ImportOptions::default()).await;
+
+    let version_downloads: Vec<_> = queries
+        .iter()
+        .filter(|s| s.contains("SET cv.downloads"))
+        .collect();
+    // Page size of 1 => one update per version, recent downloads are summed up over every day
+    assert_eq!(version_downloads.len(), 3);
+    assert!(version_downloads[0].starts_with("unwind [[1, 100, 5]] as map"));
+    let crate_downloads = queries
+        .iter()
+        .find(|s| s.contains("SET cc.downloads"))
+        .unwrap();
+    // All-time downloads as counted by crates.io, recent ones summed up over the crate's versions
+    assert!(crate_downloads.starts_with("unwind [[1, 125, 9],[2, 7, 0]] as map"));
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+// Resuming an interrupted import skips the queries it already executed, so they must be the same on every run
+#[tokio::test]
+async fn full_import_is_reproducible() {
+    let dump_path = create_fixture_dump("pipeline-reproducible", &[]);
+    let first_run = collect_queries(&dump_path, &ImportMode::Full, &ImportOptions::default()).await;
+    let second_run =
+        collect_queries(&dump_path, &ImportMode::Full, &ImportOptions::default()).await;
+    assert!(first_run.iter().any(|s| s.contains("CargoFeature")));
+    assert_eq!(first_run, second_run);
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[tokio::test]
+async fn incremental_import_only_writes_delta() {
+    let dump_path = create_fixture_dump("pipeline-incremental", &[]);
+    let existing_ids = ExistingGraphIds {
+        users: HashSet::from([1]),
+        teams: HashSet::from([1]),
+        crates: HashSet::from([1, 2]),
+        categories: HashSet::from([1]),
+        keywords: HashSet::new(),
+        crate_versions: HashSet::from([1, 2]),
+        crate_version_counts: HashMap::from([(1, 1), (2, 1)]),
+        yanked_crate_versions: HashSet::from([2]),
+        user_owners: HashSet::from([(1, 1)]),
+        team_owners: HashSet::from([(1, 2)]),
+        crate_categories: HashSet::from([(1, 1), (2, 1)]),
+        crate_keywords: HashSet::new(),
+        crate_metadata: HashMap::from([
+            (1, crate_metadata_hash([None, None, None, None])),
+            (2, crate_metadata_hash([Some("JSON"), None, None, None])),
+        ]),
+        crate_downloads: HashMap::from([(1, (125, 9)), (2, (7, 1))]),
+        crate_version_downloads: HashMap::from([(1, (100, 5)), (2, (7, 0))]),
+    };
+    let queries = collect_queries(
+        &dump_path,
+        &ImportMode::Incremental(Box::new(existing_ids)),
+        &ImportOptions::default(),
+    )
+    .await;
+
+    assert!(!queries.iter().any(|s| s.contains("create (:CargoUser")));
+    assert!(!queries.iter().any(|s| s.contains("create (:CargoCrate ")));
+    assert!(!queries.iter().any(|s| s.contains("create (:CargoTeam")));
+    assert!(!queries.iter().any(|s| s.contains("create (:CargoCategory")));
+    assert!(queries.iter().any(|s| s.contains("create (:CargoKeyword")));
+    // Only links the graph doesn't have yet are created, the ones the source doesn't list anymore are removed
+    assert!(!queries.iter().any(|s| s.contains("IN_CATEGORY")));
+    assert!(queries
+        .iter()
+        .any(|s| s.starts_with("unwind [[1, 1]] as map") && s.contains("HAS_KEYWORD")));
+    assert!(queries.iter().any(
+        |s| s.starts_with("unwind [[1, 2]] as map") && s.contains("CREATE (cu)-[:OWNS]->(cc)")
+    ));
+    assert!(queries.iter().any(
+        |s| s.starts_with("unwind [[1, 1]] as map") && s.contains("CREATE (ct)-[:OWNS]->(cc)")
+    ));
+    assert!(queries
+        .iter()
+        .any(|s| s.starts_with("unwind [[1, 2]] as map")
+            && s.contains("MATCH (:CargoTeam {id: map[0]})-[r:OWNS]->")));
+    assert_eq!(
+        queries
+            .iter()
+            .filter(|s| s.contains("DELETE r"))
+            .collect::<Vec<_>>()
+            .len(),
+        1
+    );
+    assert_eq!(
+        queries
+            .iter()
+            .filter(|s| s.contains("create (:CargoCrateVersion"))
+            .collect::<Vec<_>>()
+            .len(),
+        1
+    );
+    // 2 -> 1 is replaced by 2 -> 3
+    assert!(queries
+        .iter()
+        .any(|s| s.starts_with("unwind [[2, 1,") && s.ends_with("DELETE d")));
+    assert!(queries
+        .iter()
+        .any(|s| s.starts_with("unwind [[2, 3,") && s.contains("CREATE (cv_from)-[:DEPENDS_ON")));
+    // Metadata is only refreshed where it changed
+    let metadata_updates: Vec<_> = queries
+        .iter()
+        .filter(|s| s.contains("SET cc.description"))
+        .collect();
+    assert_eq!(metadata_updates.len(), 1);
+    assert!(metadata_updates[0].starts_with("unwind [[2,null,"));
+    // Downloads are only set where they changed (and on new versions)
+    let downloads_updates: Vec<_> = queries
+        .iter()
+        .filter(|s| s.contains("SET cv.downloads") || s.contains("SET cc.downloads"))
+        .collect();
+    assert_eq!(downloads_updates.len(), 2);
+    assert!(downloads_updates[0].starts_with("unwind [[3, 20, 4]] as map"));
+    assert!(downloads_updates[1].starts_with("unwind [[2, 7, 0]] as map"));
+    // Version 2 was unyanked
+    assert!(queries
+        .iter()
+        .any(|s| s.starts_with("unwind [[2, false]]") && s.contains("SET cv.yanked")));
+    // Latest version of serde moved from 1 to 3
+    assert!(queries
+        .iter()
+        .any(|s| s.starts_with("unwind [1]") && s.contains("LATEST_VERSION")));
+
+    std::fs::remove_file(dump_path).unwrap();
+}
+
+#[test]
+fn kept_lookups_shrink_later_pages() {
+    // 64 MB => 10922 rows per page while nothing is kept
+    let budget = IngestionBudget::from_megabytes(64, 250_000);
+    assert_eq!(budget.page_size, 10922);
+    assert_eq!(budget.page_size_after(&KeptEntries::default()), 10922);
+
+    // Half of the budget taken by the version cache
+    let kept = KeptEntries {
+        cached_versions: 262_144,
+        ..Default::default()
+    };
+    assert_eq!(budget.page_size_after(&kept), 5461);
+
+    // Never below the minimal page size, nor above the configured one
+    let kept = KeptEntries {
+        cached_versions: 10_000_000,
+        ..Default::default()
+    };
+    assert_eq!(budget.page_size_after(&kept), 1000);
+    let small_pages = IngestionBudget {
+        page_size: 1,
+        ..budget
+    };
+    assert_eq!(small_pages.page_size_after(&kept), 1);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::create_fixture_dump;
+use std::{assert_eq, collections::BTreeMap};
+
+use data_preprocessor::pipeline::{run_import, ImportMode, ImportOptions, IngestionBudget};
+use data_preprocessor::source::DataSource;
+use data_preprocessor::stats::ImportStats;
+
+#[tokio::test]
+async fn graph_counts_are_compared_with_source() {
+    let dump_path = create_fixture_dump(
+        "stats",
+        &[
+            ("crates", "downloads,id,name\n0,1,serde\n0,2,broken\n"),
+            (
+                "versions",
+                "crate_id,features,id,num,published_by,yanked\n1,{},1,1.0.0,1,f\n2,{},2,one,1,f\n",
+            ),
+            ("version_downloads", "date,downloads,version_id\n"),
+            (
+                "dependencies",
+                "crate_id,default_features,explicit_name,features,id,kind,optional,req,target,version_id\n1,t,,{},1,0,f,^1.0,,2\n2,t,,{},2,0,f,^1.0,,1\n",
+            ),
+        ],
+    );
+    let mut queries: Vec<String> = vec![];
+    let counts = run_import(
+        &DataSource::DbDump(dump_path.clone()),
+        &ImportMode::Full,
+        &IngestionBudget::default(),
+        &ImportOptions::default(),
+        &mut queries,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        counts.crates_without_parseable_versions,
+        vec![(2, "broken".to_owned())]
+    );
+
+    let graph_counts = |counts: &[(&str, usize)]| -> BTreeMap<String, usize> {
+        counts.iter().map(|(s, c)| (s.to_string(), *c)).collect()
+    };
+    let stats = ImportStats::new(
+        "test".into(),
+        false,
+        &ImportOptions::default(),
+        &counts,
+        graph_counts(&[
+            ("CargoUser", 1),
+            ("CargoCrate", 2),
+            ("CargoCrateVersion", 1),
+        ]),
+        graph_counts(&[("DEPENDS_ON", 1), ("VERSION", 2)]),
+    );
+    assert_eq!(stats.source_rows.dependencies, 2);
+    assert_eq!(stats.unresolved_dependencies, 1);
+    assert_eq!(stats.crates_without_parseable_versions, vec!["broken"]);
+    assert_eq!(
+        stats.discrepancies,
+        vec!["1 CargoCrateVersion in the graph, but 2 crate versions in the source"]
+    );
+
+    // Incremental imports have to keep the options of the graph they update
+    stats
+        .ensure_same_options(&ImportOptions::default())
+        .unwrap();
+    assert!(stats
+        .ensure_same_options(&ImportOptions {
+            minimal_versions: true,
+            ..Default::default()
+        })
+        .is_err());
+
+    std::fs::remove_file(dump_path).unwrap();
+}
@@ -1,55 +1,20 @@
-use data_preprocessor::utils::connect_db_dependencies;
+#[macro_use]
+mod common;
+
+use chrono::NaiveDate;
+use data_preprocessor::constants::REDIS_INSERTION_CHUNK_SIZE;
+use data_preprocessor::unresolved::UnresolvedReason;
+use data_preprocessor::utils::{
+    connect_db_dependencies, gen_feature_enables_redis_graph_link_query,
+    gen_features_redis_graph_node_query, gen_remove_dependency_redis_graph_link_query,
+    ResolutionStrategy, VersionCache,
+};
 use std::{assert_eq, collections::HashMap};
 
 use data_preprocessor::models::{
-    CargoCrateVersionDBResponse, CargoDependenciesDBResponse, CargoDependencyKind,
-    CargoDependencyRGEdgeBuilder,
+    CargoCrateVersionDBResponse, CargoDependenciesDBResponse, CargoDependencyRGEdgeBuilder,
 };
 
-// Important for versions: id, create_id, num
-// Important for depenedencies: required_semver, to_crate_id
-macro_rules! quick_crate_version {
-    ($id:expr, $crate_id:expr, $num:expr) => {
-        CargoCrateVersionDBResponse {
-            id: $id,
-            crate_id: $crate_id,
-            num: $num.into(),
-            features: sqlx::types::Json(HashMap::new()),
-            published_by: None,
-            crate_name: "".into(),
-        }
-    };
-}
-
-macro_rules! quick_dependency {
-    ($to:expr, $req:expr) => {
-        CargoDependenciesDBResponse {
-            from_version_id: 1,
-            to_crate_id: $to,
-            required_semver: $req.into(),
-            optional: false,
-            default_features: false,
-            features: Vec::new(),
-            target: None,
-            kind: CargoDependencyKind::Normal,
-        }
-    };
-}
-
-macro_rules! quick_edge {
-    ($to:expr, $req:expr) => {
-        CargoDependencyRGEdgeBuilder {
-            from_version_id: 1,
-            to_version_id: $to,
-            required_semver: $req.into(),
-            optional: false,
-            with_features: Vec::new(),
-            target: None,
-            kind: CargoDependencyKind::Normal,
-        }
-    };
-}
-
 #[test]
 fn basic_db_dependency_connection() {
     let db_crate_versions = vec![
@@ -60,15 +25,73 @@ fn basic_db_dependency_connection() {
 
     let db_dependencies_1 = vec![quick_dependency![1, "^1.0.0"]];
     let expected_output_1: Vec<CargoDependencyRGEdgeBuilder> = vec![quick_edge![3, "^1.0.0"]];
-    let output_1 = connect_db_dependencies(&db_crate_versions, &db_dependencies_1);
+    let output_1 = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies_1,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
     assert_eq!(output_1, expected_output_1);
 
     let db_dependencies_2 = vec![quick_dependency![1, "<=1.0.0"]];
     let expected_output_2: Vec<CargoDependencyRGEdgeBuilder> = vec![quick_edge![1, "<=1.0.0"]];
-    let output_2 = connect_db_dependencies(&db_crate_versions, &db_dependencies_2);
+    let output_2 = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies_2,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
     assert_eq!(output_2, expected_output_2);
 }
 
+#[test]
+fn unresolved_dependencies_are_reported() {
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![2, 2, "not semver"],
+    ];
+
+    let db_dependencies = vec![
+        quick_dependency![1, "^1.0.0"],
+        quick_dependency![1, "not a requirement"],
+        quick_dependency![1, "^2.0.0"],
+        quick_dependency![2, "*"],
+        quick_dependency![3, "*"],
+    ];
+
+    let connected = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    );
+    assert_eq!(connected.edges, vec![quick_edge![1, "^1.0.0"]]);
+    assert_eq!(connected.report.resolved, 1);
+    assert_eq!(
+        connected
+            .report
+            .unresolved
+            .iter()
+            .map(|s| (s.to_crate_id, s.reason))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, UnresolvedReason::BadRequirement),
+            (1, UnresolvedReason::NoMatchingVersion),
+            (2, UnresolvedReason::UnparsableVersionNum),
+            (3, UnresolvedReason::UnknownCrate),
+        ]
+    );
+    assert!(connected.report.unresolved[0].error.is_some());
+    assert_eq!(
+        connected.report.to_json("test")["unresolved_by_reason"],
+        serde_json::json!({
+            "bad_requirement": 1,
+            "unknown_crate": 1,
+            "unparsable_version_num": 1,
+            "no_matching_version": 1,
+        })
+    );
+}
+
 #[test]
 fn multiple_versions_and_dependencies() {
     let db_crate_versions = vec![
@@ -95,6 +118,389 @@ fn multiple_versions_and_dependencies() {
         quick_edge![4, "~2.0.0"],
     ];
 
-    let output = connect_db_dependencies(&db_crate_versions, &db_dependencies);
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn backported_patch_releases() {
+    // 1.0.5 and 0.9.1 were published (higher id) after 1.1.0 and 2.0.0
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "0.9.0"],
+        quick_crate_version![2, 1, "1.0.0"],
+        quick_crate_version![3, 1, "1.1.0"],
+        quick_crate_version![4, 1, "2.0.0"],
+        quick_crate_version![5, 1, "1.0.5"],
+        quick_crate_version![6, 1, "0.9.1"],
+    ];
+
+    let db_dependencies = vec![
+        quick_dependency![1, "^1.0"],
+        quick_dependency![1, "~1.0"],
+        quick_dependency![1, "^0.9"],
+        quick_dependency![1, "<2.0.0"],
+        quick_dependency![1, "*"],
+    ];
+
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> = vec![
+        quick_edge![3, "^1.0"],
+        quick_edge![5, "~1.0"],
+        quick_edge![6, "^0.9"],
+        quick_edge![3, "<2.0.0"],
+        quick_edge![4, "*"],
+    ];
+
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn minimal_versions() {
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "0.9.0"],
+        quick_crate_version![2, 1, "1.0.0", true],
+        quick_crate_version![3, 1, "1.0.1"],
+        quick_crate_version![4, 1, "1.1.0"],
+        quick_crate_version![5, 1, "2.0.0-alpha.1"],
+    ];
+
+    let db_dependencies = vec![
+        quick_dependency![1, "^1.0"],
+        quick_dependency![1, "=1.0.0"],
+        quick_dependency![1, ">=0.9.0"],
+        quick_dependency![1, "^2.0.0-alpha.1"],
+    ];
+
+    // Yanked versions and pre-releases are still avoided when anything else matches
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> = vec![
+        quick_edge![3, "^1.0"],
+        quick_edge![2, "=1.0.0"],
+        quick_edge![1, ">=0.9.0"],
+        quick_edge![5, "^2.0.0-alpha.1"],
+    ];
+
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Minimal,
+    )
+    .edges;
     assert_eq!(output, expected_output);
 }
+
+#[test]
+fn as_of_publish_date() {
+    let published_on = |id, crate_id, num: &str, day| CargoCrateVersionDBResponse {
+        created_at: NaiveDate::from_ymd_opt(2017, 4, day).and_then(|s| s.and_hms_opt(12, 0, 0)),
+        ..quick_crate_version![id, crate_id, num]
+    };
+    let db_crate_versions = vec![
+        published_on(1, 1, "1.0.0", 1),
+        published_on(2, 1, "1.1.0", 10),
+        published_on(3, 1, "1.2.0", 20),
+        // Published after the depending versions
+        published_on(4, 1, "1.0.1", 25),
+        // Depending versions
+        published_on(10, 2, "1.0.0", 15),
+        published_on(11, 2, "1.1.0", 2),
+        quick_crate_version![12, 2, "1.2.0"],
+    ];
+
+    let db_dependencies = vec![
+        CargoDependenciesDBResponse {
+            from_version_id: 10,
+            ..quick_dependency![1, "^1.0"]
+        },
+        CargoDependenciesDBResponse {
+            from_version_id: 11,
+            ..quick_dependency![1, "~1.0"]
+        },
+        // Nothing is known to have existed without a publish date
+        CargoDependenciesDBResponse {
+            from_version_id: 12,
+            ..quick_dependency![1, "^1.0"]
+        },
+    ];
+
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> = vec![
+        CargoDependencyRGEdgeBuilder {
+            from_version_id: 10,
+            ..quick_edge![2, "^1.0"]
+        },
+        CargoDependencyRGEdgeBuilder {
+            from_version_id: 11,
+            ..quick_edge![1, "~1.0"]
+        },
+    ];
+
+    let connected = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::AsOfPublishDate,
+    );
+    assert_eq!(connected.edges, expected_output);
+    assert_eq!(connected.report.unresolved.len(), 1);
+}
+
+#[test]
+fn versions_in_random_order() {
+    // Dump rows are not ordered, resolution must not depend on insertion order
+    let db_crate_versions = vec![
+        quick_crate_version![3, 1, "1.2.0"],
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![4, 1, "1.10.0"],
+        quick_crate_version![2, 1, "1.1.0"],
+    ];
+
+    let db_dependencies = vec![quick_dependency![1, "^1.0.0"], quick_dependency![1, "<1.2"]];
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> =
+        vec![quick_edge![4, "^1.0.0"], quick_edge![2, "<1.2"]];
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn pre_releases_are_matched_like_cargo() {
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "0.9.0"],
+        quick_crate_version![2, 1, "1.0.0-alpha.1"],
+        quick_crate_version![3, 1, "1.0.0-rc.1"],
+        quick_crate_version![4, 1, "1.0.0"],
+        quick_crate_version![5, 1, "1.1.0-beta.1"],
+        quick_crate_version![6, 2, "0.1.0-alpha.1"],
+        quick_crate_version![7, 2, "0.1.0-alpha.2"],
+    ];
+
+    let db_dependencies = vec![
+        // Pre-releases are not matched unless explicitly requested
+        quick_dependency![1, "^1.0.0"],
+        quick_dependency![1, "*"],
+        quick_dependency![1, ">=0.9.0"],
+        // Explicit pre-release on the same major.minor.patch
+        quick_dependency![1, "=1.0.0-alpha.1"],
+        quick_dependency![1, "^1.1.0-beta.1"],
+        // Pre-release on a different major.minor.patch doesn't open up 1.1.0-beta.1
+        quick_dependency![1, "^1.0.0-alpha.1"],
+        // Stable versions are preferred over matching pre-releases
+        quick_dependency![1, ">=0.9.0, <=1.0.0-rc.1"],
+        // Only pre-releases published
+        quick_dependency![2, "^0.1.0-alpha.1"],
+        quick_dependency![2, "^0.1.0"],
+    ];
+
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> = vec![
+        quick_edge![4, "^1.0.0"],
+        quick_edge![4, "*"],
+        quick_edge![4, ">=0.9.0"],
+        quick_edge![2, "=1.0.0-alpha.1"],
+        quick_edge![5, "^1.1.0-beta.1"],
+        quick_edge![4, "^1.0.0-alpha.1"],
+        quick_edge![1, ">=0.9.0, <=1.0.0-rc.1"],
+        quick_edge![7, "^0.1.0-alpha.1"],
+    ];
+
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn yanked_versions_are_last_resort() {
+    let db_crate_versions = vec![
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![2, 1, "1.1.0", true],
+        quick_crate_version![3, 1, "1.2.0-rc.1"],
+        quick_crate_version![4, 1, "2.0.0", true],
+        quick_crate_version![5, 1, "3.0.0-alpha.1", true],
+    ];
+
+    let db_dependencies = vec![
+        quick_dependency![1, "^1.0.0"],
+        quick_dependency![1, "^1.2.0-rc.1"],
+        // Nothing else matches, same as Cargo with a lockfile
+        quick_dependency![1, "^2.0.0"],
+        quick_dependency![1, "=1.1.0"],
+        quick_dependency![1, "^3.0.0-alpha.1"],
+    ];
+
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> = vec![
+        quick_edge![1, "^1.0.0"],
+        quick_edge![3, "^1.2.0-rc.1"],
+        quick_edge![4, "^2.0.0"],
+        quick_edge![2, "=1.1.0"],
+        quick_edge![5, "^3.0.0-alpha.1"],
+    ];
+
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn renamed_dependencies_keep_local_name() {
+    let db_crate_versions = vec![CargoCrateVersionDBResponse {
+        crate_name: "serde".into(),
+        ..quick_crate_version![1, 1, "1.0.0"]
+    }];
+
+    let db_dependencies = vec![
+        quick_dependency![1, "^1.0.0"],
+        CargoDependenciesDBResponse {
+            explicit_name: Some("serde1".into()),
+            ..quick_dependency![1, "=1.0.0"]
+        },
+    ];
+
+    let expected_output: Vec<CargoDependencyRGEdgeBuilder> = vec![
+        CargoDependencyRGEdgeBuilder {
+            name: "serde".into(),
+            ..quick_edge![1, "^1.0.0"]
+        },
+        CargoDependencyRGEdgeBuilder {
+            name: "serde1".into(),
+            ..quick_edge![1, "=1.0.0"]
+        },
+    ];
+
+    let output = connect_db_dependencies(
+        &db_crate_versions,
+        &db_dependencies,
+        ResolutionStrategy::Maximal,
+    )
+    .edges;
+    assert_eq!(output, expected_output);
+}
+
+#[test]
+fn features_are_linked_to_declared_features_only() {
+    let db_crate_versions = vec![CargoCrateVersionDBResponse {
+        features: sqlx::types::Json(HashMap::from([
+            ("default".into(), vec!["std".into(), "serde".into()]),
+            ("std".into(), vec!["serde?/std".into()]),
+        ])),
+        ..quick_crate_version![1, 1, "1.0.0"]
+    }];
+
+    let feature_nodes =
+        gen_features_redis_graph_node_query(&db_crate_versions, REDIS_INSERTION_CHUNK_SIZE)
+            .unwrap();
+    assert_eq!(feature_nodes.len(), 1);
+    assert!(feature_nodes[0].starts_with(
+        r#"unwind [[1, "default", ["std","serde"], ["serde"]],[1, "std", ["serde?/std"], []]] as map"#
+    ));
+
+    // "serde" is an optional dependency, not a feature, it's linked once dependencies are resolved
+    let feature_links =
+        gen_feature_enables_redis_graph_link_query(&db_crate_versions, REDIS_INSERTION_CHUNK_SIZE)
+            .unwrap();
+    assert_eq!(feature_links.len(), 1);
+    assert!(feature_links[0].starts_with(r#"unwind [[1, "default", "std"]] as map"#));
+}
+
+#[test]
+fn removed_dependencies_are_told_apart() {
+    // Same crate, requirement and kind, once renamed and optional
+    let removed = vec![
+        quick_edge![2, "^1.0"],
+        CargoDependencyRGEdgeBuilder {
+            name: "serde1".into(),
+            optional: true,
+            ..quick_edge![2, "^1.0"]
+        },
+    ];
+
+    let queries = gen_remove_dependency_redis_graph_link_query(
+        &removed,
+        ResolutionStrategy::Maximal,
+        REDIS_INSERTION_CHUNK_SIZE,
+    )
+    .unwrap();
+    assert_eq!(queries.len(), 1);
+    assert!(queries[0].starts_with(
+        r#"unwind [[1, 2, 0, "", "^1.0", "", false],[1, 2, 0, "", "^1.0", "serde1", true]] as map"#
+    ));
+    assert!(queries[0].contains("AND d.optional = map[6]"));
+}
+
+#[test]
+fn latest_version_follows_semver() {
+    let version_cache = VersionCache::new(&[
+        quick_crate_version![1, 1, "1.0.0"],
+        quick_crate_version![2, 1, "2.0.0"],
+        // Backported after 2.0.0
+        quick_crate_version![3, 1, "1.0.1"],
+        quick_crate_version![4, 1, "3.0.0-beta.1"],
+        quick_crate_version![5, 1, "2.1.0", true],
+        // Nothing stable and non-yanked
+        quick_crate_version![6, 2, "0.1.0", true],
+        quick_crate_version![7, 2, "0.2.0-alpha"],
+        // Nothing parseable
+        quick_crate_version![8, 3, "one"],
+        quick_crate_version![9, 3, "two"],
+    ]);
+
+    // No publish dates, so the first versions are the lowest ids
+    assert_eq!(
+        version_cache.first_or_latest_versions(false),
+        vec![(1, 1), (2, 6), (3, 8)]
+    );
+    assert_eq!(
+        version_cache.first_or_latest_versions(true),
+        vec![(1, 2), (2, 7), (3, 9)]
+    );
+}
+
+#[test]
+fn first_version_follows_publish_order() {
+    let published_on = |id, crate_id, num: &str, day| CargoCrateVersionDBResponse {
+        created_at: NaiveDate::from_ymd_opt(2017, 4, day).and_then(|s| s.and_hms_opt(12, 0, 0)),
+        ..quick_crate_version![id, crate_id, num]
+    };
+    let version_cache = VersionCache::new(&[
+        // A lower version published later
+        published_on(1, 1, "1.0.0", 5),
+        published_on(2, 1, "0.9.0", 10),
+        // A lower id published later
+        published_on(3, 2, "0.2.0", 7),
+        published_on(4, 2, "0.1.0", 3),
+        // Published at the same time
+        published_on(6, 3, "0.1.0", 1),
+        published_on(5, 3, "0.2.0", 1),
+        // Version nums don't have to parse
+        published_on(7, 4, "one", 1),
+        published_on(8, 4, "0.1.0", 2),
+    ]);
+
+    assert_eq!(
+        version_cache.first_or_latest_versions(false),
+        vec![(1, 1), (2, 4), (3, 5), (4, 7)]
+    );
+    // Still the highest version
+    assert_eq!(
+        version_cache.first_or_latest_versions(true),
+        vec![(1, 1), (2, 3), (3, 5), (4, 8)]
+    );
+}